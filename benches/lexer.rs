@@ -0,0 +1,51 @@
+//! Lexes multi-megabyte sources of increasing size. Run with `cargo bench`.
+//!
+//! Throughput should stay roughly constant as the input grows; the old
+//! `chars().nth()` based lexer slowed down quadratically and could not get
+//! through even the smallest of these inputs in reasonable time.
+
+use std::time::Instant;
+
+use interpreter_rust::lexer::lexer::Lexer;
+use interpreter_rust::token::token::Token;
+
+const SNIPPET: &str = r#"let fibonacci = fn(x) {
+  if (x < 2) { return x; }
+  fibonacci(x - 1) + fibonacci(x - 2);
+};
+let greeting = "héllo, wörld 🐒";
+let pairs = {"one": 1, "two": [2, 3], true: !false};
+puts(len(greeting), pairs["two"][0] * 10 / 2 != 9);
+"#;
+
+const MIB: usize = 1024 * 1024;
+
+fn source_of_size(bytes: usize) -> String {
+    SNIPPET.repeat(bytes / SNIPPET.len() + 1)
+}
+
+fn lex_all(source: &str) -> usize {
+    let mut lexer = Lexer::new(source);
+    let mut count = 0;
+    loop {
+        let token = lexer.next_token().expect("token");
+        count += 1;
+        if token == Token::Eof {
+            return count;
+        }
+    }
+}
+
+fn main() {
+    for size in [1, 2, 4, 8] {
+        let source = source_of_size(size * MIB);
+        let start = Instant::now();
+        let tokens = lex_all(&source);
+        let elapsed = start.elapsed();
+        let throughput = source.len() as f64 / MIB as f64 / elapsed.as_secs_f64();
+        println!(
+            "{} MiB: {} tokens in {:.2?} ({:.1} MiB/s)",
+            size, tokens, elapsed, throughput
+        );
+    }
+}
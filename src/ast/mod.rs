@@ -4,6 +4,9 @@ pub mod ast {
 
     use core::fmt;
 
+    use num_bigint::BigInt;
+
+    use crate::span::Span;
     use crate::token::token::Token;
 
     use std::fmt::Formatter;
@@ -30,43 +33,90 @@ pub mod ast {
     }
 
     #[derive(Debug, Clone, PartialEq)]
-    pub enum Statement {
+    pub struct Statement {
+        pub kind: StatementKind,
+        pub span: Span,
+    }
+
+    impl Statement {
+        pub fn new(kind: StatementKind, span: Span) -> Self {
+            Statement { kind, span }
+        }
+    }
+
+    impl fmt::Display for Statement {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.kind)
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum StatementKind {
         Let(String, Expression),
         Return(Expression),
         Expr(Expression),
+        Break,
+        Continue,
     }
 
-    impl fmt::Display for Statement {
+    impl fmt::Display for StatementKind {
         fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
             match self {
-                Statement::Let(id, expr) => write!(f, "let {} = {};", id, expr),
-                Statement::Return(expr) => write!(f, "return {};", expr),
-                Statement::Expr(expr) => write!(f, "{}", expr),
+                StatementKind::Let(id, expr) => write!(f, "let {} = {};", id, expr),
+                StatementKind::Return(expr) => write!(f, "return {};", expr),
+                StatementKind::Expr(expr) => write!(f, "{}", expr),
+                StatementKind::Break => write!(f, "break;"),
+                StatementKind::Continue => write!(f, "continue;"),
             }
         }
     }
 
     #[derive(Debug, Clone, PartialEq)]
-    pub enum Expression {
+    pub struct Expression {
+        pub kind: ExpressionKind,
+        pub span: Span,
+    }
+
+    impl Expression {
+        pub fn new(kind: ExpressionKind, span: Span) -> Self {
+            Expression { kind, span }
+        }
+    }
+
+    impl fmt::Display for Expression {
+        fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+            write!(f, "{}", self.kind)
+        }
+    }
+
+    #[derive(Debug, Clone, PartialEq)]
+    pub enum ExpressionKind {
         Identifier(String),
         Lit(Literal),
         Prefix(Token, Box<Expression>),
         Infix(Token, Box<Expression>, Box<Expression>),
         Postfix(Token, Box<Expression>),
         If(Box<Expression>, BlockStatement, Option<BlockStatement>),
+        While(Box<Expression>, BlockStatement),
+        For(String, Box<Expression>, BlockStatement),
+        Range(Box<Expression>, Box<Expression>),
+        /// `target op value`, where `op` is `=` or a compound operator such as
+        /// `+=`, and `target` is an identifier or an index expression.
+        Assign(Token, Box<Expression>, Box<Expression>),
         Fn(Vec<String>, BlockStatement),
         Call(Box<Expression>, Vec<Expression>),
+        Index(Box<Expression>, Box<Expression>),
     }
 
-    impl fmt::Display for Expression {
+    impl fmt::Display for ExpressionKind {
         fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
             match self {
-                Expression::Identifier(s) => write!(f, "{}", s),
-                Expression::Lit(l) => write!(f, "{}", l),
-                Expression::Prefix(op, expr) => write!(f, "({}{})", op, expr),
-                Expression::Infix(op, e1, e2) => write!(f, "({} {} {})", e1, op, e2),
-                Expression::Postfix(op, e) => write!(f, "({}{})", e, op),
-                Expression::If(cond, cons, alt) => {
+                ExpressionKind::Identifier(s) => write!(f, "{}", s),
+                ExpressionKind::Lit(l) => write!(f, "{}", l),
+                ExpressionKind::Prefix(op, expr) => write!(f, "({}{})", op, expr),
+                ExpressionKind::Infix(op, e1, e2) => write!(f, "({} {} {})", e1, op, e2),
+                ExpressionKind::Postfix(op, e) => write!(f, "({}{})", e, op),
+                ExpressionKind::If(cond, cons, alt) => {
                     if let Some(alt) = alt {
                         write!(
                             f,
@@ -79,19 +129,36 @@ pub mod ast {
                         write!(f, "if {} {{ {} }}", cond, format_statements(cons))
                     }
                 }
-                Expression::Fn(params, _body) => {
+                ExpressionKind::While(cond, body) => {
+                    write!(f, "while {} {{ {} }}", cond, format_statements(body))
+                }
+                ExpressionKind::For(name, iterable, body) => write!(
+                    f,
+                    "for {} in {} {{ {} }}",
+                    name,
+                    iterable,
+                    format_statements(body)
+                ),
+                ExpressionKind::Range(start, end) => write!(f, "({}..{})", start, end),
+                ExpressionKind::Assign(op, target, value) => {
+                    write!(f, "({} {} {})", target, op, value)
+                }
+                ExpressionKind::Fn(params, _body) => {
                     write!(f, "fn({}) {{...}}", params.join(", "))
                 }
-                Expression::Call(fn_expr, args) => {
+                ExpressionKind::Call(fn_expr, args) => {
                     write!(f, "{}({})", fn_expr, format_expressions(args))
                 }
+                ExpressionKind::Index(left, index) => write!(f, "({}[{}])", left, index),
             }
         }
     }
 
     #[derive(Debug, Clone, PartialEq)]
     pub enum Literal {
-        Int(i32),
+        Int(i64),
+        BigInt(BigInt),
+        Float(f64),
         String(String),
         Bool(bool),
         Array(Vec<Expression>),
@@ -102,15 +169,18 @@ pub mod ast {
         fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
             match self {
                 Literal::Int(i) => write!(f, "{}", i),
+                Literal::BigInt(b) => write!(f, "{}", b),
+                Literal::Float(x) => write!(f, "{:?}", x),
                 Literal::String(s) => write!(f, "{}", s),
                 Literal::Bool(b) => write!(f, "{}", b),
                 Literal::Array(a) => write!(f, "[{}]", format_expressions(a)),
                 Literal::Hash(h) => {
-                    let mut result = String::new();
-                    for (k, v) in h {
-                        result.push_str(&format!("{}: {}", k, v));
-                    }
-                    write!(f, "{{{}}}", result)
+                    let pairs = h
+                        .iter()
+                        .map(|(k, v)| format!("{}: {}", k, v))
+                        .collect::<Vec<String>>()
+                        .join(", ");
+                    write!(f, "{{{}}}", pairs)
                 }
             }
         }
@@ -136,13 +206,20 @@ fn format_expressions(expressions: &[Expression]) -> String {
 #[cfg(test)]
 mod test {
 
-    use super::ast::{Expression, Statement};
+    use super::ast::{Expression, ExpressionKind, Statement, StatementKind};
+    use crate::span::Span;
 
     #[test]
     fn display() {
-        let p = vec![Statement::Let(
-            "asdf".to_string(),
-            Expression::Identifier("bar".to_string()),
+        let p = vec![Statement::new(
+            StatementKind::Let(
+                "asdf".to_string(),
+                Expression::new(
+                    ExpressionKind::Identifier("bar".to_string()),
+                    Span::default(),
+                ),
+            ),
+            Span::default(),
         )];
 
         let expected = "let asdf = bar;";
@@ -0,0 +1,202 @@
+use std::fmt::Write;
+
+pub type Instructions = Vec<u8>;
+
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OpCode {
+    Constant,
+    Pop,
+    True,
+    False,
+    Null,
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Equal,
+    NotEqual,
+    GreaterThan,
+    LessThan,
+    GreaterEqual,
+    LessEqual,
+    Mod,
+    Pow,
+    Minus,
+    Bang,
+    Jump,
+    JumpNotTruthy,
+    GetGlobal,
+    SetGlobal,
+    ReturnValue,
+    Array,
+    Hash,
+    Index,
+    Call,
+    GetLocal,
+    SetLocal,
+    GetBuiltin,
+    Closure,
+    GetFree,
+    CurrentClosure,
+}
+
+const OPCODES: &[OpCode] = &[
+    OpCode::Constant,
+    OpCode::Pop,
+    OpCode::True,
+    OpCode::False,
+    OpCode::Null,
+    OpCode::Add,
+    OpCode::Sub,
+    OpCode::Mul,
+    OpCode::Div,
+    OpCode::Equal,
+    OpCode::NotEqual,
+    OpCode::GreaterThan,
+    OpCode::LessThan,
+    OpCode::GreaterEqual,
+    OpCode::LessEqual,
+    OpCode::Mod,
+    OpCode::Pow,
+    OpCode::Minus,
+    OpCode::Bang,
+    OpCode::Jump,
+    OpCode::JumpNotTruthy,
+    OpCode::GetGlobal,
+    OpCode::SetGlobal,
+    OpCode::ReturnValue,
+    OpCode::Array,
+    OpCode::Hash,
+    OpCode::Index,
+    OpCode::Call,
+    OpCode::GetLocal,
+    OpCode::SetLocal,
+    OpCode::GetBuiltin,
+    OpCode::Closure,
+    OpCode::GetFree,
+    OpCode::CurrentClosure,
+];
+
+impl OpCode {
+    pub fn from_byte(byte: u8) -> Option<OpCode> {
+        OPCODES.get(byte as usize).copied()
+    }
+
+    /// Width in bytes of each operand that follows the opcode.
+    pub fn operand_widths(self) -> &'static [usize] {
+        match self {
+            OpCode::Constant
+            | OpCode::Jump
+            | OpCode::JumpNotTruthy
+            | OpCode::GetGlobal
+            | OpCode::SetGlobal
+            | OpCode::Array
+            | OpCode::Hash => &[2],
+            OpCode::Call
+            | OpCode::GetLocal
+            | OpCode::SetLocal
+            | OpCode::GetBuiltin
+            | OpCode::GetFree => &[1],
+            // The function's constant and how many free variables it captures.
+            OpCode::Closure => &[2, 1],
+            _ => &[],
+        }
+    }
+}
+
+/// Encodes `op` and its operands, or returns `None` if an operand does not
+/// fit in its width.
+pub fn make(op: OpCode, operands: &[usize]) -> Option<Instructions> {
+    let mut instruction = vec![op as u8];
+    for (operand, width) in operands.iter().zip(op.operand_widths()) {
+        match width {
+            1 => instruction.push(u8::try_from(*operand).ok()?),
+            2 => instruction.extend_from_slice(&u16::try_from(*operand).ok()?.to_be_bytes()),
+            _ => unreachable!("unsupported operand width {}", width),
+        }
+    }
+    Some(instruction)
+}
+
+pub fn read_u16(instructions: &[u8], offset: usize) -> usize {
+    u16::from_be_bytes([instructions[offset], instructions[offset + 1]]) as usize
+}
+
+/// Reads an operand of `width` bytes at `offset`.
+pub fn read_operand(instructions: &[u8], offset: usize, width: usize) -> usize {
+    match width {
+        1 => instructions[offset] as usize,
+        _ => read_u16(instructions, offset),
+    }
+}
+
+/// Renders `instructions` one per line, prefixed with their byte offset.
+pub fn disassemble(instructions: &[u8]) -> String {
+    let mut out = String::new();
+    let mut ip = 0;
+    while ip < instructions.len() {
+        let Some(op) = OpCode::from_byte(instructions[ip]) else {
+            let _ = writeln!(out, "{:04} ERROR: unknown opcode {}", ip, instructions[ip]);
+            ip += 1;
+            continue;
+        };
+        let _ = write!(out, "{:04} {:?}", ip, op);
+        ip += 1;
+        for width in op.operand_widths() {
+            let _ = write!(out, " {}", read_operand(instructions, ip, *width));
+            ip += width;
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_make() {
+        assert_eq!(make(OpCode::Constant, &[65534]), Some(vec![0, 255, 254]));
+        assert_eq!(make(OpCode::Add, &[]), Some(vec![OpCode::Add as u8]));
+        assert_eq!(make(OpCode::Constant, &[65536]), None);
+        assert_eq!(
+            make(OpCode::Closure, &[65534, 255]),
+            Some(vec![OpCode::Closure as u8, 255, 254, 255])
+        );
+        assert_eq!(make(OpCode::GetLocal, &[256]), None);
+    }
+
+    #[test]
+    fn test_opcode_round_trip() {
+        for op in OPCODES {
+            assert_eq!(OpCode::from_byte(*op as u8), Some(*op));
+        }
+        assert_eq!(OpCode::from_byte(255), None);
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let instructions = [
+            make(OpCode::Add, &[]),
+            make(OpCode::Constant, &[2]),
+            make(OpCode::Constant, &[65535]),
+            make(OpCode::GetLocal, &[1]),
+            make(OpCode::Closure, &[65535, 255]),
+        ]
+        .into_iter()
+        .flatten()
+        .flatten()
+        .collect::<Vec<u8>>();
+
+        let expected = "\
+0000 Add
+0001 Constant 2
+0004 Constant 65535
+0007 GetLocal 1
+0009 Closure 65535 255
+";
+        assert_eq!(disassemble(&instructions), expected);
+    }
+}
@@ -0,0 +1,563 @@
+pub mod symbol_table;
+
+use std::mem;
+use std::rc::Rc;
+
+use self::symbol_table::{Scope, Symbol, SymbolTable};
+use crate::ast::ast::{Expression, ExpressionKind, Literal, Node, Statement, StatementKind};
+use crate::code::{make, Instructions, OpCode};
+use crate::eval::builtin;
+use crate::eval::error::{EvaluatorError, EvaluatorErrorKind};
+use crate::object::{CompiledFunction, Object};
+use crate::span::Span;
+use crate::token::token::Token;
+
+/// The output of the compiler: everything the VM needs to run a program.
+#[derive(Debug, Clone, Default)]
+pub struct Bytecode {
+    pub instructions: Instructions,
+    pub constants: Vec<Rc<Object>>,
+    /// Source locations of the instructions that can fail at runtime.
+    pub spans: Vec<(usize, Span)>,
+    pub symbols: SymbolTable,
+}
+
+/// The instructions of the program or of one function literal being compiled.
+#[derive(Default)]
+struct CompilationScope {
+    instructions: Instructions,
+    spans: Vec<(usize, Span)>,
+    last_instruction: Option<(OpCode, usize)>,
+}
+
+pub struct Compiler {
+    constants: Vec<Rc<Object>>,
+    symbols: SymbolTable,
+    /// The program, then each function literal being compiled inside it.
+    scopes: Vec<CompilationScope>,
+}
+
+impl Default for Compiler {
+    fn default() -> Self {
+        Compiler::new_with_state(SymbolTable::default(), Vec::new())
+    }
+}
+
+impl Compiler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Creates a compiler that keeps the globals and constants of an earlier
+    /// compilation, so that successive REPL lines share their bindings.
+    pub fn new_with_state(symbols: SymbolTable, constants: Vec<Rc<Object>>) -> Self {
+        Compiler {
+            constants,
+            symbols,
+            scopes: vec![CompilationScope::default()],
+        }
+    }
+
+    pub fn bytecode(mut self) -> Bytecode {
+        // A failed compilation can stop inside a function literal.
+        while self.scopes.len() > 1 {
+            self.leave_scope();
+        }
+        let program = self.scopes.remove(0);
+        Bytecode {
+            instructions: program.instructions,
+            constants: self.constants,
+            spans: program.spans,
+            symbols: self.symbols,
+        }
+    }
+
+    pub fn compile(&mut self, node: &Node) -> Result<(), EvaluatorError> {
+        match node {
+            Node::Program(statements) => {
+                for statement in statements {
+                    self.compile_statement(statement)
+                        .map_err(|err| err.or_span(statement.span))?;
+                }
+                Ok(())
+            }
+            Node::Statement(statement) => self
+                .compile_statement(statement)
+                .map_err(|err| err.or_span(statement.span)),
+            Node::Expr(expr) => {
+                self.compile_expression(expr)?;
+                self.emit(OpCode::Pop, &[])?;
+                Ok(())
+            }
+        }
+    }
+
+    fn compile_statement(&mut self, statement: &Statement) -> Result<(), EvaluatorError> {
+        match &statement.kind {
+            StatementKind::Let(name, expr) => {
+                match &expr.kind {
+                    ExpressionKind::Fn(params, body) => {
+                        self.compile_function(params, body, Some(name))?
+                    }
+                    _ => self.compile_expression(expr)?,
+                }
+                let symbol = self.symbols.define(name);
+                match symbol.scope {
+                    Scope::Global => self.emit(OpCode::SetGlobal, &[symbol.index])?,
+                    _ => self.emit(OpCode::SetLocal, &[symbol.index])?,
+                };
+                // `let` evaluates to null, like it does in the tree-walker.
+                self.emit(OpCode::Null, &[])?;
+                self.emit(OpCode::Pop, &[])?;
+            }
+            StatementKind::Return(expr) => {
+                self.compile_expression(expr)?;
+                self.emit(OpCode::ReturnValue, &[])?;
+            }
+            StatementKind::Expr(expr) => {
+                self.compile_expression(expr)?;
+                self.emit(OpCode::Pop, &[])?;
+            }
+            StatementKind::Break | StatementKind::Continue => {
+                let construct = statement.to_string();
+                return Err(
+                    EvaluatorError::new(EvaluatorErrorKind::UnsupportedByCompiler(construct))
+                        .or_span(statement.span),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn compile_expression(&mut self, expr: &Expression) -> Result<(), EvaluatorError> {
+        match &expr.kind {
+            ExpressionKind::Identifier(name) => self.load_name(name, expr.span)?,
+            ExpressionKind::Lit(Literal::Int(i)) => {
+                let index = self.add_constant(Object::Integer(*i));
+                self.emit(OpCode::Constant, &[index])?;
+            }
+            ExpressionKind::Lit(Literal::BigInt(b)) => {
+                let index = self.add_constant(Object::from(b.clone()));
+                self.emit(OpCode::Constant, &[index])?;
+            }
+            ExpressionKind::Lit(Literal::Float(x)) => {
+                let index = self.add_constant(Object::Float(*x));
+                self.emit(OpCode::Constant, &[index])?;
+            }
+            ExpressionKind::Lit(Literal::String(s)) => {
+                let index = self.add_constant(Object::String(s.clone()));
+                self.emit(OpCode::Constant, &[index])?;
+            }
+            ExpressionKind::Lit(Literal::Bool(true)) => {
+                self.emit(OpCode::True, &[])?;
+            }
+            ExpressionKind::Lit(Literal::Bool(false)) => {
+                self.emit(OpCode::False, &[])?;
+            }
+            ExpressionKind::Prefix(op, right) => {
+                self.compile_expression(right)?;
+                let opcode = match op {
+                    Token::Bang => OpCode::Bang,
+                    Token::Minus => OpCode::Minus,
+                    _ => return Err(unsupported(expr)),
+                };
+                self.emit_spanned(opcode, &[], expr.span)?;
+            }
+            ExpressionKind::Infix(Token::And, left, right) => {
+                // Any falsy operand jumps straight to `false`.
+                self.compile_expression(left)?;
+                let left_falsy = self.emit(OpCode::JumpNotTruthy, &[0])?;
+                self.compile_expression(right)?;
+                let right_falsy = self.emit(OpCode::JumpNotTruthy, &[0])?;
+                self.emit(OpCode::True, &[])?;
+                let done = self.emit(OpCode::Jump, &[0])?;
+                self.patch_jump(left_falsy)?;
+                self.patch_jump(right_falsy)?;
+                self.emit(OpCode::False, &[])?;
+                self.patch_jump(done)?;
+            }
+            ExpressionKind::Infix(Token::Or, left, right) => {
+                // A truthy left operand skips the right one entirely.
+                self.compile_expression(left)?;
+                let left_falsy = self.emit(OpCode::JumpNotTruthy, &[0])?;
+                let left_truthy = self.emit(OpCode::Jump, &[0])?;
+                self.patch_jump(left_falsy)?;
+                self.compile_expression(right)?;
+                let right_falsy = self.emit(OpCode::JumpNotTruthy, &[0])?;
+                self.patch_jump(left_truthy)?;
+                self.emit(OpCode::True, &[])?;
+                let done = self.emit(OpCode::Jump, &[0])?;
+                self.patch_jump(right_falsy)?;
+                self.emit(OpCode::False, &[])?;
+                self.patch_jump(done)?;
+            }
+            ExpressionKind::Infix(op, left, right) => {
+                self.compile_expression(left)?;
+                self.compile_expression(right)?;
+                let opcode = match op {
+                    Token::Plus => OpCode::Add,
+                    Token::Minus => OpCode::Sub,
+                    Token::Asterisk => OpCode::Mul,
+                    Token::Slash => OpCode::Div,
+                    Token::Eq => OpCode::Equal,
+                    Token::NotEq => OpCode::NotEqual,
+                    Token::Gt => OpCode::GreaterThan,
+                    Token::Lt => OpCode::LessThan,
+                    Token::GtEq => OpCode::GreaterEqual,
+                    Token::LtEq => OpCode::LessEqual,
+                    Token::Percent => OpCode::Mod,
+                    Token::Power => OpCode::Pow,
+                    _ => return Err(unsupported(expr)),
+                };
+                self.emit_spanned(opcode, &[], expr.span)?;
+            }
+            ExpressionKind::If(condition, consequence, alternative) => {
+                self.compile_expression(condition)?;
+                let jump_not_truthy = self.emit(OpCode::JumpNotTruthy, &[0])?;
+                self.compile_block(consequence)?;
+                let jump = self.emit(OpCode::Jump, &[0])?;
+                self.patch_jump(jump_not_truthy)?;
+                match alternative {
+                    Some(alternative) => self.compile_block(alternative)?,
+                    None => {
+                        self.emit(OpCode::Null, &[])?;
+                    }
+                }
+                self.patch_jump(jump)?;
+            }
+            ExpressionKind::Lit(Literal::Array(elements)) => {
+                for element in elements {
+                    self.compile_expression(element)?;
+                }
+                self.emit(OpCode::Array, &[elements.len()])?;
+            }
+            ExpressionKind::Lit(Literal::Hash(pairs)) => {
+                for (key, value) in pairs {
+                    self.compile_expression(key)?;
+                    self.compile_expression(value)?;
+                }
+                self.emit_spanned(OpCode::Hash, &[pairs.len() * 2], expr.span)?;
+            }
+            ExpressionKind::Index(left, index) => {
+                self.compile_expression(left)?;
+                self.compile_expression(index)?;
+                self.emit_spanned(OpCode::Index, &[], expr.span)?;
+            }
+            ExpressionKind::Fn(params, body) => self.compile_function(params, body, None)?,
+            ExpressionKind::Call(callee, args) => {
+                self.compile_expression(callee)?;
+                for arg in args {
+                    self.compile_expression(arg)?;
+                }
+                self.emit_spanned(OpCode::Call, &[args.len()], expr.span)?;
+            }
+            _ => return Err(unsupported(expr)),
+        }
+        Ok(())
+    }
+
+    /// Compiles a function literal into a constant and emits the instruction
+    /// that makes a closure of it. A function bound by `let` can call itself
+    /// by `name`.
+    fn compile_function(
+        &mut self,
+        params: &[String],
+        body: &[Statement],
+        name: Option<&str>,
+    ) -> Result<(), EvaluatorError> {
+        self.enter_scope();
+        if let Some(name) = name {
+            self.symbols.define_function_name(name);
+        }
+        for param in params {
+            self.symbols.define(param);
+        }
+        let compiled = self
+            .compile_block(body)
+            .and_then(|()| self.emit(OpCode::ReturnValue, &[]));
+        let (scope, num_locals, free) = self.leave_scope();
+        compiled?;
+
+        for symbol in &free {
+            self.load_symbol(*symbol, None)?;
+        }
+        let function = CompiledFunction {
+            instructions: scope.instructions,
+            spans: scope.spans,
+            parameters: params.to_vec(),
+            num_locals: num_locals.max(params.len()),
+            name: name.map(str::to_string),
+        };
+        let index = self.add_constant(Object::Closure(Rc::new(function), Vec::new()));
+        self.emit(OpCode::Closure, &[index, free.len()])?;
+        Ok(())
+    }
+
+    fn enter_scope(&mut self) {
+        self.scopes.push(CompilationScope::default());
+        self.symbols = SymbolTable::new_enclosed(mem::take(&mut self.symbols));
+    }
+
+    /// Ends the innermost function literal, returning its instructions, how
+    /// many locals it uses and the symbols it captures from the enclosing
+    /// scope.
+    fn leave_scope(&mut self) -> (CompilationScope, usize, Vec<Symbol>) {
+        let scope = self.scopes.pop().unwrap_or_default();
+        let num_locals = self.symbols.num_definitions();
+        let (outer, free) = mem::take(&mut self.symbols)
+            .into_outer()
+            .unwrap_or_default();
+        self.symbols = outer;
+        (scope, num_locals, free)
+    }
+
+    /// Emits the instruction that loads what `name` refers to. Names that
+    /// are neither bound nor builtins are taken to be globals defined later,
+    /// as a function may call one defined after it. Reading such a global
+    /// before it is set fails at runtime, as it does in the tree-walker.
+    fn load_name(&mut self, name: &str, span: Span) -> Result<(), EvaluatorError> {
+        let symbol = match self.symbols.resolve(name) {
+            Some(symbol) => symbol,
+            None => match builtin::index(name) {
+                Some(index) => {
+                    self.emit(OpCode::GetBuiltin, &[index])?;
+                    return Ok(());
+                }
+                None => self.symbols.define_global(name),
+            },
+        };
+        self.load_symbol(symbol, Some(span))
+    }
+
+    fn load_symbol(&mut self, symbol: Symbol, span: Option<Span>) -> Result<(), EvaluatorError> {
+        match symbol.scope {
+            Scope::Global => match span {
+                Some(span) => self.emit_spanned(OpCode::GetGlobal, &[symbol.index], span)?,
+                None => self.emit(OpCode::GetGlobal, &[symbol.index])?,
+            },
+            Scope::Local => self.emit(OpCode::GetLocal, &[symbol.index])?,
+            Scope::Free => self.emit(OpCode::GetFree, &[symbol.index])?,
+            Scope::Function => self.emit(OpCode::CurrentClosure, &[])?,
+        };
+        Ok(())
+    }
+
+    fn scope(&mut self) -> &mut CompilationScope {
+        let innermost = self.scopes.len() - 1;
+        &mut self.scopes[innermost]
+    }
+
+    /// Compiles a block so that it leaves its value on the stack.
+    fn compile_block(&mut self, statements: &[Statement]) -> Result<(), EvaluatorError> {
+        if statements.is_empty() {
+            self.emit(OpCode::Null, &[])?;
+            return Ok(());
+        }
+        for statement in statements {
+            self.compile_statement(statement)?;
+        }
+        let scope = self.scope();
+        if let Some((OpCode::Pop, position)) = scope.last_instruction {
+            scope.instructions.truncate(position);
+            scope.last_instruction = None;
+        }
+        Ok(())
+    }
+
+    fn add_constant(&mut self, obj: Object) -> usize {
+        self.constants.push(Rc::new(obj));
+        self.constants.len() - 1
+    }
+
+    fn emit(&mut self, op: OpCode, operands: &[usize]) -> Result<usize, EvaluatorError> {
+        let instruction = make(op, operands).ok_or_else(|| {
+            let operand = operands.iter().copied().max().unwrap_or_default();
+            EvaluatorError::new(EvaluatorErrorKind::OperandTooLarge(operand))
+        })?;
+        let scope = self.scope();
+        let position = scope.instructions.len();
+        scope.instructions.extend(instruction);
+        scope.last_instruction = Some((op, position));
+        Ok(position)
+    }
+
+    fn emit_spanned(
+        &mut self,
+        op: OpCode,
+        operands: &[usize],
+        span: Span,
+    ) -> Result<usize, EvaluatorError> {
+        let position = self.emit(op, operands)?;
+        self.scope().spans.push((position, span));
+        Ok(position)
+    }
+
+    /// Points the jump at `position` to the next instruction to be emitted.
+    fn patch_jump(&mut self, position: usize) -> Result<(), EvaluatorError> {
+        let scope = self.scope();
+        let target = scope.instructions.len();
+        let target = u16::try_from(target)
+            .map_err(|_| EvaluatorError::new(EvaluatorErrorKind::OperandTooLarge(target)))?;
+        scope.instructions[position + 1..position + 3].copy_from_slice(&target.to_be_bytes());
+        Ok(())
+    }
+}
+
+fn unsupported(expr: &Expression) -> EvaluatorError {
+    EvaluatorError::new(EvaluatorErrorKind::UnsupportedByCompiler(expr.to_string()))
+        .or_span(expr.span)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Compiler;
+    use crate::code::disassemble;
+    use crate::object::Object;
+    use crate::parser::parser::parse;
+
+    fn compile(input: &str) -> (String, Vec<String>) {
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse(input).expect("parse"))
+            .expect("compile");
+        let bytecode = compiler.bytecode();
+        let constants = bytecode.constants.iter().map(|c| c.to_string()).collect();
+        (disassemble(&bytecode.instructions), constants)
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let (instructions, constants) = compile("1 + 2; -3");
+        let expected = "\
+0000 Constant 0
+0003 Constant 1
+0006 Add
+0007 Pop
+0008 Constant 2
+0011 Minus
+0012 Pop
+";
+        assert_eq!(instructions, expected);
+        assert_eq!(constants, vec!["1", "2", "3"]);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let (instructions, _) = compile("if (true) { 10 }; 3333;");
+        let expected = "\
+0000 True
+0001 JumpNotTruthy 10
+0004 Constant 0
+0007 Jump 11
+0010 Null
+0011 Pop
+0012 Constant 1
+0015 Pop
+";
+        assert_eq!(instructions, expected);
+    }
+
+    #[test]
+    fn test_global_let_statements() {
+        let (instructions, _) = compile("let one = 1; one;");
+        let expected = "\
+0000 Constant 0
+0003 SetGlobal 0
+0006 Null
+0007 Pop
+0008 GetGlobal 0
+0011 Pop
+";
+        assert_eq!(instructions, expected);
+    }
+
+    #[test]
+    fn test_closures() {
+        let mut compiler = Compiler::new();
+        compiler
+            .compile(&parse("fn(a) { fn(b) { a + b } }(1)(2)").expect("parse"))
+            .expect("compile");
+        let bytecode = compiler.bytecode();
+        let expected = "\
+0000 Closure 1 0
+0004 Constant 2
+0007 Call 1
+0009 Constant 3
+0012 Call 1
+0014 Pop
+";
+        assert_eq!(disassemble(&bytecode.instructions), expected);
+
+        let bodies: Vec<String> = bytecode
+            .constants
+            .iter()
+            .filter_map(|constant| match constant.as_ref() {
+                Object::Closure(function, _) => Some(disassemble(&function.instructions)),
+                _ => None,
+            })
+            .collect();
+        let inner = "\
+0000 GetFree 0
+0002 GetLocal 0
+0004 Add
+0005 ReturnValue
+";
+        let outer = "\
+0000 GetLocal 0
+0002 Closure 0 1
+0006 ReturnValue
+";
+        assert_eq!(bodies, vec![inner, outer]);
+    }
+
+    #[test]
+    fn test_builtins_and_recursion() {
+        let (instructions, _) = compile("let f = fn() { f() }; len([])");
+        let expected = "\
+0000 Closure 0 0
+0004 SetGlobal 0
+0007 Null
+0008 Pop
+0009 GetBuiltin 0
+0011 Array 0
+0014 Call 1
+0016 Pop
+";
+        assert_eq!(instructions, expected);
+    }
+
+    #[test]
+    fn test_operand_too_large() {
+        let mut compiler = Compiler::new();
+        let input = "1;".repeat(70_000);
+        let err = compiler
+            .compile(&parse(&input).expect("parse"))
+            .expect_err("too many constants");
+        assert_eq!(err.code(), "E0223");
+        assert_eq!(
+            err.to_string(),
+            "program too large for the bytecode compiler: operand 65536 exceeds 65535"
+        );
+        assert_eq!(err.span().map(|span| span.start), Some(2 * 65536));
+
+        // Jump targets past the 16-bit range are rejected too.
+        let mut compiler = Compiler::new();
+        let input = format!("if (true) {{ {} }}", "true;".repeat(70_000));
+        let err = compiler
+            .compile(&parse(&input).expect("parse"))
+            .expect_err("jump too far");
+        assert_eq!(err.code(), "E0223");
+    }
+
+    #[test]
+    fn test_unsupported_expression() {
+        let mut compiler = Compiler::new();
+        let err = compiler
+            .compile(&parse("while (true) { 1 }").expect("parse"))
+            .expect_err("unsupported");
+        assert_eq!(
+            err.to_string(),
+            "not supported by the bytecode compiler: while true { 1 }"
+        );
+    }
+}
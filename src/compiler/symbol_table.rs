@@ -0,0 +1,115 @@
+use std::collections::HashMap;
+
+/// Where the VM finds the value a name refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scope {
+    Global,
+    /// A parameter or `let` binding of the function being compiled.
+    Local,
+    /// A local of an enclosing function, captured when the closure is made.
+    Free,
+    /// The function being compiled, referring to itself by its `let` name.
+    Function,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Symbol {
+    pub scope: Scope,
+    pub index: usize,
+}
+
+/// Maps binding names to their slot in the VM's globals store, or in the
+/// frame of the function being compiled.
+#[derive(Debug, Clone, Default)]
+pub struct SymbolTable {
+    outer: Option<Box<SymbolTable>>,
+    store: HashMap<String, Symbol>,
+    names: Vec<String>,
+    /// The symbols of enclosing functions that this one captures, in the
+    /// order of their `Scope::Free` indices.
+    free: Vec<Symbol>,
+}
+
+impl SymbolTable {
+    /// Creates the table for a function nested in `outer`.
+    pub fn new_enclosed(outer: SymbolTable) -> Self {
+        SymbolTable {
+            outer: Some(Box::new(outer)),
+            ..Default::default()
+        }
+    }
+
+    /// Hands back the enclosing table and the symbols this one captured from
+    /// it, or `None` for the global table.
+    pub fn into_outer(self) -> Option<(SymbolTable, Vec<Symbol>)> {
+        let outer = self.outer?;
+        Some((*outer, self.free))
+    }
+
+    /// Returns the slot for `name`, allocating one if it is new. Redefining a
+    /// name reuses its slot, which shadows the previous binding.
+    pub fn define(&mut self, name: &str) -> Symbol {
+        if let Some(symbol) = self.store.get(name) {
+            if matches!(symbol.scope, Scope::Global | Scope::Local) {
+                return *symbol;
+            }
+        }
+        let scope = match self.outer {
+            Some(_) => Scope::Local,
+            None => Scope::Global,
+        };
+        let symbol = Symbol {
+            scope,
+            index: self.names.len(),
+        };
+        self.store.insert(name.to_string(), symbol);
+        self.names.push(name.to_string());
+        symbol
+    }
+
+    /// Defines `name` in the global table, however deeply this one is nested.
+    pub fn define_global(&mut self, name: &str) -> Symbol {
+        match &mut self.outer {
+            Some(outer) => outer.define_global(name),
+            None => self.define(name),
+        }
+    }
+
+    /// Lets the function being compiled refer to itself as `name`.
+    pub fn define_function_name(&mut self, name: &str) {
+        let symbol = Symbol {
+            scope: Scope::Function,
+            index: 0,
+        };
+        self.store.insert(name.to_string(), symbol);
+    }
+
+    /// Finds what `name` refers to. Locals of enclosing functions are
+    /// captured as free variables of this one.
+    pub fn resolve(&mut self, name: &str) -> Option<Symbol> {
+        if let Some(symbol) = self.store.get(name) {
+            return Some(*symbol);
+        }
+        let symbol = self.outer.as_mut()?.resolve(name)?;
+        if symbol.scope == Scope::Global {
+            return Some(symbol);
+        }
+        let free = Symbol {
+            scope: Scope::Free,
+            index: self.free.len(),
+        };
+        self.free.push(symbol);
+        self.store.insert(name.to_string(), free);
+        Some(free)
+    }
+
+    /// The name of the global in slot `index`.
+    pub fn name(&self, index: usize) -> Option<&str> {
+        self.names.get(index).map(String::as_str)
+    }
+
+    /// How many slots this table has allocated.
+    pub fn num_definitions(&self) -> usize {
+        self.names.len()
+    }
+}
@@ -0,0 +1,306 @@
+use std::fmt::Write;
+
+use crate::eval::error::{EvaluatorError, Frame};
+use crate::parser::error::ParserError;
+use crate::span::Span;
+
+const RED: &str = "\x1b[1;31m";
+const BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+/// An error report that can be rendered against the source it came from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Diagnostic {
+    pub code: Option<String>,
+    pub message: String,
+    pub span: Option<Span>,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Diagnostic {
+            message: message.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_code(mut self, code: impl Into<String>) -> Self {
+        self.code = Some(code.into());
+        self
+    }
+
+    pub fn with_span(mut self, span: Span) -> Self {
+        self.span = Some(span);
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn with_help(mut self, help: impl Into<String>) -> Self {
+        self.help = Some(help.into());
+        self
+    }
+
+    /// Renders the diagnostic with the offending line of `source` underlined.
+    /// When `color` is set the output contains ANSI escape sequences.
+    pub fn render(&self, source: &str, color: bool) -> String {
+        let paint = |style: &str, text: &str| {
+            if color {
+                format!("{}{}{}", style, text, RESET)
+            } else {
+                text.to_string()
+            }
+        };
+
+        let mut out = String::new();
+        let header = match &self.code {
+            Some(code) => format!("error[{}]", code),
+            None => "error".to_string(),
+        };
+        let _ = writeln!(
+            out,
+            "{}{}",
+            paint(RED, &header),
+            paint(BOLD, &format!(": {}", self.message))
+        );
+
+        let line = self
+            .span
+            .and_then(|span| Some((span, source.lines().nth(span.line.checked_sub(1)?)?)));
+        let gutter_width = line.map_or(0, |(span, _)| span.line.to_string().len());
+        let gutter = " ".repeat(gutter_width);
+
+        if let Some((span, text)) = line {
+            let underline = underline_width(source, span, text);
+            let _ = writeln!(out, "{}{} {}", gutter, paint(BLUE, "-->"), span);
+            let _ = writeln!(out, "{} {}", gutter, paint(BLUE, "|"));
+            let _ = writeln!(out, "{} {}", paint(BLUE, &format!("{} |", span.line)), text);
+            let _ = writeln!(
+                out,
+                "{} {} {}{}",
+                gutter,
+                paint(BLUE, "|"),
+                " ".repeat(span.column.saturating_sub(1)),
+                paint(RED, &"^".repeat(underline))
+            );
+        }
+
+        for note in &self.notes {
+            let _ = writeln!(out, "{} {} note: {}", gutter, paint(BLUE, "="), note);
+        }
+        if let Some(help) = &self.help {
+            let _ = writeln!(out, "{} {} help: {}", gutter, paint(BLUE, "="), help);
+        }
+        out
+    }
+}
+
+/// Number of carets needed to underline `span`, clipped to the end of `line`.
+fn underline_width(source: &str, span: Span, line: &str) -> usize {
+    let remaining = line
+        .chars()
+        .count()
+        .saturating_sub(span.column.saturating_sub(1));
+    let width = source
+        .get(span.start..span.end)
+        .map_or(1, |text| text.chars().take_while(|c| *c != '\n').count());
+    width.min(remaining).max(1)
+}
+
+/// Consecutive identical traceback entries beyond this many are collapsed.
+const REPEATED_FRAMES_SHOWN: usize = 3;
+
+/// Renders the calls active when `err` occurred, most recent call last, as
+/// Python does. Each entry names a function and the line of `source` it had
+/// reached. Returns an empty string when the error happened outside of any
+/// function call.
+pub fn render_traceback(err: &EvaluatorError, source: &str, color: bool) -> String {
+    if err.trace().is_empty() {
+        return String::new();
+    }
+
+    // Frame `i` (outermost first) was called from the function entered by
+    // frame `i - 1`, and the innermost function had reached the error itself.
+    let frames: Vec<&Frame> = err.trace().iter().rev().collect();
+    let names = std::iter::once("<program>").chain(frames.iter().map(|f| f.function.as_str()));
+    let locations = frames
+        .iter()
+        .map(|frame| Some(frame.call_site))
+        .chain(std::iter::once(err.span()));
+    let entries: Vec<(&str, Option<Span>)> = names.zip(locations).collect();
+
+    let mut out = String::new();
+    let header = "Traceback (most recent call last):";
+    if color {
+        let _ = writeln!(out, "{}{}{}", BOLD, header, RESET);
+    } else {
+        let _ = writeln!(out, "{}", header);
+    }
+
+    let mut repeats = 0;
+    for (i, entry) in entries.iter().enumerate() {
+        if i > 0 && entries[i - 1] == *entry {
+            repeats += 1;
+        } else {
+            write_repeats(&mut out, repeats);
+            repeats = 0;
+        }
+        if repeats >= REPEATED_FRAMES_SHOWN {
+            continue;
+        }
+
+        let (function, span) = entry;
+        match span {
+            Some(span) => {
+                let _ = writeln!(
+                    out,
+                    "  line {}, column {}, in {}",
+                    span.line, span.column, function
+                );
+                if let Some(text) = span.line.checked_sub(1).and_then(|n| source.lines().nth(n)) {
+                    let _ = writeln!(out, "    {}", text.trim());
+                }
+            }
+            None => {
+                let _ = writeln!(out, "  in {}", function);
+            }
+        }
+    }
+    write_repeats(&mut out, repeats);
+    out
+}
+
+fn write_repeats(out: &mut String, repeats: usize) {
+    if repeats >= REPEATED_FRAMES_SHOWN {
+        let hidden = repeats + 1 - REPEATED_FRAMES_SHOWN;
+        let _ = writeln!(out, "  [Previous line repeated {} more times]", hidden);
+    }
+}
+
+impl From<&ParserError> for Diagnostic {
+    fn from(err: &ParserError) -> Self {
+        Diagnostic::new(err.to_string())
+            .with_code(err.code())
+            .with_span(err.span())
+    }
+}
+
+impl From<&EvaluatorError> for Diagnostic {
+    fn from(err: &EvaluatorError) -> Self {
+        let diagnostic = Diagnostic::new(err.to_string()).with_code(err.code());
+        match err.span() {
+            Some(span) => diagnostic.with_span(span),
+            None => diagnostic,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{render_traceback, Diagnostic};
+    use crate::parser::parser::parse;
+    use crate::runner::{run, RunError};
+    use crate::span::Span;
+
+    #[test]
+    fn test_render_parser_error() {
+        let source = "let x = 1;\nlet y = (1 + 2;";
+        let errors = parse(source).expect_err("missing paren");
+        let rendered = Diagnostic::from(&errors[0]).render(source, false);
+
+        let expected = "\
+error[E0101]: expected next token to be ), but got ; instead
+ --> 2:15
+  |
+2 | let y = (1 + 2;
+  |               ^
+";
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn test_render_code_notes_and_help() {
+        let source = "let total = price * count;";
+        let rendered = Diagnostic::new("identifier not found: price")
+            .with_code("E0200")
+            .with_span(Span::new(12, 17, 1, 13))
+            .with_note("bindings must be declared before they are used")
+            .with_help("declare it with `let price = ...;`")
+            .render(source, false);
+
+        let expected = "\
+error[E0200]: identifier not found: price
+ --> 1:13
+  |
+1 | let total = price * count;
+  |             ^^^^^
+  = note: bindings must be declared before they are used
+  = help: declare it with `let price = ...;`
+";
+        assert_eq!(rendered, expected);
+    }
+
+    #[test]
+    fn test_render_without_span() {
+        let rendered = Diagnostic::new("something went wrong").render("", false);
+        assert_eq!(rendered, "error: something went wrong\n");
+    }
+
+    #[test]
+    fn test_render_with_color() {
+        let rendered = Diagnostic::new("oops")
+            .with_span(Span::new(0, 1, 1, 1))
+            .render("x", true);
+        assert!(rendered.starts_with("\x1b[1;31merror\x1b[0m"));
+        assert!(rendered.contains("\x1b[1;31m^\x1b[0m"));
+    }
+
+    #[test]
+    fn test_render_traceback() {
+        let source = "let inner = fn(x) { 10 / x };\nlet outer = fn(x) { inner(x) };\nouter(0)";
+        let err = match run(source) {
+            Err(RunError::Runtime(err)) => err,
+            other => panic!("expected a runtime error, got {:?}", other),
+        };
+
+        let expected = "\
+Traceback (most recent call last):
+  line 3, column 1, in <program>
+    outer(0)
+  line 2, column 21, in outer
+    let outer = fn(x) { inner(x) };
+  line 1, column 21, in inner
+    let inner = fn(x) { 10 / x };
+";
+        assert_eq!(render_traceback(&err, source, false), expected);
+    }
+
+    #[test]
+    fn test_render_traceback_collapses_recursion() {
+        let source = "let f = fn(n) { if (n == 0) { 1 + true } else { f(n - 1) } };\nf(5)";
+        let err = match run(source) {
+            Err(RunError::Runtime(err)) => err,
+            other => panic!("expected a runtime error, got {:?}", other),
+        };
+
+        let rendered = render_traceback(&err, source, false);
+        assert_eq!(rendered.matches("column 49, in f").count(), 3);
+        assert!(rendered.contains("  [Previous line repeated 2 more times]\n"));
+        assert!(rendered.ends_with("line 1, column 31, in f\n    let f = fn(n) { if (n == 0) { 1 + true } else { f(n - 1) } };\n"));
+    }
+
+    #[test]
+    fn test_no_traceback_outside_functions() {
+        let err = match run("1 + true") {
+            Err(RunError::Runtime(err)) => err,
+            other => panic!("expected a runtime error, got {:?}", other),
+        };
+        assert_eq!(render_traceback(&err, "1 + true", false), "");
+    }
+}
@@ -1 +1,121 @@
+use std::rc::Rc;
 
+use super::error::{EvaluatorError, EvaluatorErrorKind};
+use super::{limits, EvaluatorResult};
+use crate::object::{BuiltinFunction, Object};
+
+const BUILTINS: &[(&str, BuiltinFunction)] = &[
+    ("len", len),
+    ("first", first),
+    ("last", last),
+    ("rest", rest),
+    ("push", push),
+    ("puts", puts),
+];
+
+pub fn lookup(name: &str) -> Option<Rc<Object>> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(name, func)| Rc::new(Object::Builtin(name, *func)))
+}
+
+/// The position of `name` among the builtins, by which compiled code refers
+/// to it.
+pub fn index(name: &str) -> Option<usize> {
+    BUILTINS.iter().position(|(builtin, _)| *builtin == name)
+}
+
+/// The builtin at `index`, see [`index`].
+pub fn get(index: usize) -> Option<Rc<Object>> {
+    BUILTINS
+        .get(index)
+        .map(|(name, func)| Rc::new(Object::Builtin(name, *func)))
+}
+
+pub fn names() -> Vec<&'static str> {
+    BUILTINS.iter().map(|(name, _)| *name).collect()
+}
+
+fn check_arity(args: &[Rc<Object>], expected: usize) -> Result<(), EvaluatorError> {
+    if args.len() != expected {
+        return Err(EvaluatorErrorKind::WrongArgumentCount {
+            expected,
+            got: args.len(),
+        }
+        .into());
+    }
+    Ok(())
+}
+
+fn unsupported(name: &str, arg: &Object) -> EvaluatorError {
+    EvaluatorErrorKind::UnsupportedArgument {
+        builtin: name.to_string(),
+        type_name: arg.type_name(),
+    }
+    .into()
+}
+
+fn len(args: Vec<Rc<Object>>) -> EvaluatorResult {
+    check_arity(&args, 1)?;
+    match args[0].as_ref() {
+        Object::String(s) => Ok(Rc::new(Object::Integer(s.chars().count() as i64))),
+        Object::Array(elements) => Ok(Rc::new(Object::Integer(elements.len() as i64))),
+        Object::Hash(pairs) => Ok(Rc::new(Object::Integer(pairs.len() as i64))),
+        arg => Err(unsupported("len", arg)),
+    }
+}
+
+fn first(args: Vec<Rc<Object>>) -> EvaluatorResult {
+    check_arity(&args, 1)?;
+    match args[0].as_ref() {
+        Object::Array(elements) => Ok(elements
+            .first()
+            .map(Rc::clone)
+            .unwrap_or_else(|| Rc::new(Object::Null))),
+        arg => Err(unsupported("first", arg)),
+    }
+}
+
+fn last(args: Vec<Rc<Object>>) -> EvaluatorResult {
+    check_arity(&args, 1)?;
+    match args[0].as_ref() {
+        Object::Array(elements) => Ok(elements
+            .last()
+            .map(Rc::clone)
+            .unwrap_or_else(|| Rc::new(Object::Null))),
+        arg => Err(unsupported("last", arg)),
+    }
+}
+
+fn rest(args: Vec<Rc<Object>>) -> EvaluatorResult {
+    check_arity(&args, 1)?;
+    match args[0].as_ref() {
+        Object::Array(elements) if elements.is_empty() => Ok(Rc::new(Object::Null)),
+        Object::Array(elements) => {
+            limits::charge(elements.len())?;
+            Ok(Rc::new(Object::Array(elements[1..].to_vec())))
+        }
+        arg => Err(unsupported("rest", arg)),
+    }
+}
+
+fn push(args: Vec<Rc<Object>>) -> EvaluatorResult {
+    check_arity(&args, 2)?;
+    match args[0].as_ref() {
+        Object::Array(elements) => {
+            limits::charge(elements.len())?;
+            let mut elements = elements.clone();
+            elements.push(Rc::clone(&args[1]));
+            Ok(Rc::new(Object::Array(elements)))
+        }
+        arg => Err(unsupported("push", arg)),
+    }
+}
+
+fn puts(args: Vec<Rc<Object>>) -> EvaluatorResult {
+    for arg in args {
+        println!("{}", arg);
+    }
+    Ok(Rc::new(Object::Null))
+}
@@ -13,9 +13,10 @@ pub struct Environment {
 
 impl Environment {
     pub fn new_enclosed(outer: &Env) -> Self {
-        let mut env: Environment = Default::default();
-        env.outer = Some(Rc::clone(outer));
-        env
+        Environment {
+            outer: Some(Rc::clone(outer)),
+            ..Default::default()
+        }
     }
 
     pub fn get(&self, name: &str) -> Option<Rc<Object>> {
@@ -31,4 +32,17 @@ impl Environment {
     pub fn set(&mut self, name: &str, obj: Rc<Object>) {
         self.store.insert(name.to_string(), obj);
     }
+
+    /// Rebinds `name` in the nearest scope that already defines it. Returns
+    /// `false` if no enclosing scope does.
+    pub fn assign(&mut self, name: &str, obj: Rc<Object>) -> bool {
+        if let Some(slot) = self.store.get_mut(name) {
+            *slot = obj;
+            return true;
+        }
+        match &self.outer {
+            Some(outer) => outer.borrow_mut().assign(name, obj),
+            None => false,
+        }
+    }
 }
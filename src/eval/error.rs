@@ -1,16 +1,356 @@
-use std::fmt;
+use std::fmt::{self, Write};
 
+use super::limits::Limit;
+use crate::object::Object;
+use crate::span::Span;
+use crate::token::token::Token;
+
+#[derive(Debug)]
+pub struct EvaluatorError {
+    kind: Box<EvaluatorErrorKind>,
+    span: Option<Span>,
+    trace: Vec<Frame>,
+}
+
+/// A function call that was still running when an error occurred.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    /// The name the function was called by, or `<anonymous>`.
+    pub function: String,
+    /// The call expression that entered the function.
+    pub call_site: Span,
+}
+
+impl Frame {
+    pub fn new(function: impl Into<String>, call_site: Span) -> Self {
+        Frame {
+            function: function.into(),
+            call_site,
+        }
+    }
+}
+
+/// Operands are rendered up to this many bytes, so that an error about a
+/// large collection stays cheap.
+pub const MAX_OPERAND_LEN: usize = 256;
+
+/// A value involved in an error, recorded by its type and as it printed.
+/// Errors own no objects of the program, so they can be sent to another
+/// thread and do not keep large values alive.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operand {
+    pub type_name: &'static str,
+    /// The value as displayed, ending in `...` if it was longer than
+    /// [`MAX_OPERAND_LEN`] bytes.
+    pub text: String,
+}
+
+impl Operand {
+    pub fn new(obj: &Object) -> Self {
+        let mut text = BoundedText(String::new());
+        if write!(text, "{}", obj).is_err() {
+            text.0.push_str("...");
+        }
+        Operand {
+            type_name: obj.type_name(),
+            text: text.0,
+        }
+    }
+}
+
+impl fmt::Display for Operand {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+/// Collects at most [`MAX_OPERAND_LEN`] bytes, failing the write once full
+/// so that formatting stops early.
+struct BoundedText(String);
+
+impl Write for BoundedText {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let room = MAX_OPERAND_LEN - self.0.len();
+        if s.len() <= room {
+            self.0.push_str(s);
+            return Ok(());
+        }
+        let mut end = room;
+        while !s.is_char_boundary(end) {
+            end -= 1;
+        }
+        self.0.push_str(&s[..end]);
+        Err(fmt::Error)
+    }
+}
+
+/// What went wrong at runtime. Every kind has a stable code in the `E02xx`
+/// range, see [`EvaluatorErrorKind::code`]. Operands are recorded as an
+/// [`Operand`] so callers can still inspect their types.
 #[derive(Debug)]
-pub struct EvaluatorError(String);
+pub enum EvaluatorErrorKind {
+    UnknownIdentifier(String),
+    UndeclaredAssignment(String),
+    InvalidAssignmentTarget(String),
+    TypeMismatch {
+        op: Token,
+        left: Operand,
+        right: Operand,
+    },
+    UnknownInfixOperator {
+        op: Token,
+        left: Operand,
+        right: Operand,
+    },
+    UnknownPrefixOperator {
+        op: Token,
+        operand: Operand,
+    },
+    DivisionByZero {
+        op: Token,
+        left: Operand,
+        right: Operand,
+    },
+    NegativeExponent {
+        base: Operand,
+        exponent: Operand,
+    },
+    ExponentTooLarge {
+        base: Operand,
+        exponent: Operand,
+    },
+    /// An arithmetic result larger than `eval::MAX_INTEGER_BITS`, holding
+    /// an estimate of the bits it would have needed.
+    IntegerTooLarge {
+        op: Token,
+        bits: u64,
+    },
+    WrongArgumentCount {
+        expected: usize,
+        got: usize,
+    },
+    /// A builtin called with an argument of a type it does not handle.
+    UnsupportedArgument {
+        builtin: String,
+        type_name: &'static str,
+    },
+    NotAFunction(Operand),
+    NotIterable(Operand),
+    UnusableHashKey(&'static str),
+    IndexNotSupported {
+        collection: Operand,
+        index: Operand,
+    },
+    IndexOutOfRange {
+        collection: Operand,
+        index: Operand,
+    },
+    IndexAssignmentNotSupported {
+        collection: Operand,
+        index: Operand,
+    },
+    RangeBounds {
+        start: Operand,
+        end: Operand,
+    },
+    /// A construct the bytecode compiler cannot translate yet.
+    UnsupportedByCompiler(String),
+    UnknownOpcode(u8),
+    StackUnderflow,
+    /// Expressions or function calls nested deeper than the configured limit,
+    /// or than the configured stack allows.
+    RecursionLimit(usize),
+    /// A run stopped by its step budget, deadline or cancellation handle.
+    LimitExceeded(Limit),
+    /// A constant index, global index or jump target too large for the
+    /// bytecode's 16-bit operands.
+    OperandTooLarge(usize),
+    /// A `break` or `continue` with no loop around it. The parser rejects
+    /// these, so only syntax trees built by hand can produce this.
+    LoopControlOutsideLoop(Token),
+    /// An expression the evaluator has no rules for, such as a postfix
+    /// operator. The parser never produces these either.
+    UnsupportedExpression(String),
+}
+
+impl EvaluatorErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            EvaluatorErrorKind::UnknownIdentifier(_) => "E0200",
+            EvaluatorErrorKind::UndeclaredAssignment(_) => "E0201",
+            EvaluatorErrorKind::InvalidAssignmentTarget(_) => "E0202",
+            EvaluatorErrorKind::TypeMismatch { .. } => "E0203",
+            EvaluatorErrorKind::UnknownInfixOperator { .. } => "E0204",
+            EvaluatorErrorKind::UnknownPrefixOperator { .. } => "E0205",
+            EvaluatorErrorKind::DivisionByZero { .. } => "E0206",
+            EvaluatorErrorKind::NegativeExponent { .. } => "E0207",
+            EvaluatorErrorKind::ExponentTooLarge { .. } => "E0208",
+            EvaluatorErrorKind::WrongArgumentCount { .. } => "E0209",
+            EvaluatorErrorKind::UnsupportedArgument { .. } => "E0210",
+            EvaluatorErrorKind::NotAFunction(_) => "E0211",
+            EvaluatorErrorKind::NotIterable(_) => "E0212",
+            EvaluatorErrorKind::UnusableHashKey(_) => "E0213",
+            EvaluatorErrorKind::IndexNotSupported { .. } => "E0214",
+            EvaluatorErrorKind::IndexOutOfRange { .. } => "E0215",
+            EvaluatorErrorKind::IndexAssignmentNotSupported { .. } => "E0216",
+            EvaluatorErrorKind::RangeBounds { .. } => "E0217",
+            EvaluatorErrorKind::UnsupportedByCompiler(_) => "E0218",
+            EvaluatorErrorKind::UnknownOpcode(_) => "E0219",
+            EvaluatorErrorKind::StackUnderflow => "E0220",
+            EvaluatorErrorKind::RecursionLimit(_) => "E0221",
+            EvaluatorErrorKind::LimitExceeded(_) => "E0222",
+            EvaluatorErrorKind::OperandTooLarge(_) => "E0223",
+            EvaluatorErrorKind::IntegerTooLarge { .. } => "E0224",
+            EvaluatorErrorKind::LoopControlOutsideLoop(_) => "E0225",
+            EvaluatorErrorKind::UnsupportedExpression(_) => "E0226",
+        }
+    }
+}
+
+impl fmt::Display for EvaluatorErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvaluatorErrorKind::UnknownIdentifier(name) => {
+                write!(f, "identifier not found: {}", name)
+            }
+            EvaluatorErrorKind::UndeclaredAssignment(name) => {
+                write!(f, "assignment to undeclared identifier: {}", name)
+            }
+            EvaluatorErrorKind::InvalidAssignmentTarget(target) => {
+                write!(f, "invalid assignment target: {}", target)
+            }
+            EvaluatorErrorKind::TypeMismatch { op, left, right } => {
+                write!(f, "type mismatch: {} {} {}", left, op, right)
+            }
+            EvaluatorErrorKind::UnknownInfixOperator { op, left, right } => {
+                write!(f, "unknown operator: {} {} {}", left, op, right)
+            }
+            EvaluatorErrorKind::UnknownPrefixOperator { op, operand } => {
+                write!(f, "unknown operator: {}{}", op, operand)
+            }
+            EvaluatorErrorKind::DivisionByZero { op, left, right } => {
+                write!(f, "division by zero: {} {} {}", left, op, right)
+            }
+            EvaluatorErrorKind::NegativeExponent { base, exponent } => {
+                write!(f, "negative exponent: {} ** {}", base, exponent)
+            }
+            EvaluatorErrorKind::ExponentTooLarge { base, exponent } => {
+                write!(f, "exponent too large: {} ** {}", base, exponent)
+            }
+            EvaluatorErrorKind::IntegerTooLarge { op, bits } => write!(
+                f,
+                "integer too large: `{}` would produce about {} bits, the limit is {}",
+                op,
+                bits,
+                super::MAX_INTEGER_BITS
+            ),
+            EvaluatorErrorKind::WrongArgumentCount { expected, got } => write!(
+                f,
+                "wrong number of arguments: expected {}, got {}",
+                expected, got
+            ),
+            EvaluatorErrorKind::UnsupportedArgument { builtin, type_name } => write!(
+                f,
+                "argument to `{}` not supported, got {}",
+                builtin, type_name
+            ),
+            EvaluatorErrorKind::NotAFunction(obj) => write!(f, "not a function: {}", obj),
+            EvaluatorErrorKind::NotIterable(obj) => write!(f, "not iterable: {}", obj),
+            EvaluatorErrorKind::UnusableHashKey(type_name) => {
+                write!(f, "unusable as hash key: {}", type_name)
+            }
+            EvaluatorErrorKind::IndexNotSupported { collection, index } => {
+                write!(f, "index operator not supported: {}[{}]", collection, index)
+            }
+            EvaluatorErrorKind::IndexOutOfRange { collection, index } => {
+                write!(f, "index out of range: {}[{}]", collection, index)
+            }
+            EvaluatorErrorKind::IndexAssignmentNotSupported { collection, index } => {
+                write!(
+                    f,
+                    "index assignment not supported: {}[{}]",
+                    collection, index
+                )
+            }
+            EvaluatorErrorKind::RangeBounds { start, end } => {
+                write!(f, "range bounds must be integers: {}..{}", start, end)
+            }
+            EvaluatorErrorKind::UnsupportedByCompiler(construct) => {
+                write!(f, "not supported by the bytecode compiler: {}", construct)
+            }
+            EvaluatorErrorKind::UnknownOpcode(byte) => write!(f, "unknown opcode: {}", byte),
+            EvaluatorErrorKind::StackUnderflow => write!(f, "stack underflow"),
+            EvaluatorErrorKind::RecursionLimit(limit) => {
+                write!(
+                    f,
+                    "maximum recursion depth exceeded: the limit is {}",
+                    limit
+                )
+            }
+            EvaluatorErrorKind::LimitExceeded(limit) => write!(f, "{}", limit),
+            EvaluatorErrorKind::OperandTooLarge(operand) => write!(
+                f,
+                "program too large for the bytecode compiler: operand {} exceeds {}",
+                operand,
+                u16::MAX
+            ),
+            EvaluatorErrorKind::LoopControlOutsideLoop(keyword) => {
+                write!(f, "`{}` outside of a loop", keyword)
+            }
+            EvaluatorErrorKind::UnsupportedExpression(expr) => {
+                write!(f, "unsupported expression: {}", expr)
+            }
+        }
+    }
+}
 
 impl fmt::Display for EvaluatorError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.kind)
     }
 }
 
 impl EvaluatorError {
-    pub fn new(msg: String) -> Self {
-        EvaluatorError(msg)
+    pub fn new(kind: EvaluatorErrorKind) -> Self {
+        EvaluatorError {
+            kind: Box::new(kind),
+            span: None,
+            trace: Vec::new(),
+        }
+    }
+
+    pub fn kind(&self) -> &EvaluatorErrorKind {
+        &self.kind
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn span(&self) -> Option<Span> {
+        self.span
+    }
+
+    /// Attaches `span` unless a more precise one was already recorded.
+    pub fn or_span(mut self, span: Span) -> Self {
+        self.span.get_or_insert(span);
+        self
+    }
+
+    /// The calls that were active when the error occurred, innermost first.
+    pub fn trace(&self) -> &[Frame] {
+        &self.trace
+    }
+
+    /// Records that the error unwound out of `frame`.
+    pub fn with_frame(mut self, frame: Frame) -> Self {
+        self.trace.push(frame);
+        self
+    }
+}
+
+impl From<EvaluatorErrorKind> for EvaluatorError {
+    fn from(kind: EvaluatorErrorKind) -> Self {
+        EvaluatorError::new(kind)
     }
 }
@@ -0,0 +1,270 @@
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use super::error::{EvaluatorError, EvaluatorErrorKind};
+use crate::stack::{StackGuard, DEFAULT_MAX_STACK};
+
+/// How deeply function calls may nest when no other limit is configured.
+/// This fits in the stack the command-line interpreter runs on, even in
+/// unoptimised builds; the smaller [`DEFAULT_MAX_STACK`] runs out first.
+pub const DEFAULT_MAX_DEPTH: usize = 1024;
+
+/// The deadline and cancellation handle are only consulted once every this
+/// many steps, since reading the clock on every step would be expensive.
+const CHECK_INTERVAL: u64 = 1024;
+
+/// How many elements, bytes or 64-bit integer digits an operation on a large
+/// value may touch for each step it is charged.
+const SIZE_PER_STEP: u64 = 64;
+
+/// Settings for a run of the evaluator, see [`super::eval_with`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct EvalConfig {
+    /// How deeply function calls may nest before evaluation fails with
+    /// [`EvaluatorErrorKind::RecursionLimit`]. Nesting within a function is
+    /// bounded by `max_stack` alone.
+    pub max_depth: usize,
+    /// How many bytes of stack a run may use before it fails with
+    /// [`EvaluatorErrorKind::RecursionLimit`], whatever its depth. Keep this
+    /// well below the size of the thread's stack.
+    pub max_stack: usize,
+    /// How many steps a run may take. Evaluating an expression and starting
+    /// a loop iteration each take one step, and operations that copy or
+    /// compute large values take more in proportion to their size.
+    pub max_steps: Option<u64>,
+    /// When a run must have finished by.
+    pub deadline: Option<Instant>,
+    /// How long each run may take, counted from its start. Applies together
+    /// with `deadline`, whichever comes first.
+    pub timeout: Option<Duration>,
+    /// A handle that stops a run from another thread when cancelled.
+    pub cancel: Option<CancelHandle>,
+}
+
+impl Default for EvalConfig {
+    fn default() -> Self {
+        EvalConfig {
+            max_depth: DEFAULT_MAX_DEPTH,
+            max_stack: DEFAULT_MAX_STACK,
+            max_steps: None,
+            deadline: None,
+            timeout: None,
+            cancel: None,
+        }
+    }
+}
+
+impl EvalConfig {
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_depth = max_depth;
+        self
+    }
+
+    pub fn with_max_stack(mut self, max_stack: usize) -> Self {
+        self.max_stack = max_stack;
+        self
+    }
+
+    pub fn with_max_steps(mut self, max_steps: u64) -> Self {
+        self.max_steps = Some(max_steps);
+        self
+    }
+
+    pub fn with_deadline(mut self, deadline: Instant) -> Self {
+        self.deadline = Some(deadline);
+        self
+    }
+
+    /// Gives every run `timeout` from the moment it starts, so one config
+    /// can be reused for many runs.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn with_cancel(mut self, cancel: CancelHandle) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+}
+
+/// Stops a run of the evaluator from another thread. Clones share the same
+/// flag, so keep one and pass another in an [`EvalConfig`].
+#[derive(Debug, Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn new() -> Self {
+        CancelHandle::default()
+    }
+
+    /// Makes every run using this handle fail with [`Limit::Cancelled`]
+    /// shortly after.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+impl PartialEq for CancelHandle {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+/// Which limit of an [`EvalConfig`] stopped a run, reported through
+/// [`EvaluatorErrorKind::LimitExceeded`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    /// The step budget, holding the number of steps that were allowed.
+    Steps(u64),
+    Deadline,
+    Cancelled,
+}
+
+impl fmt::Display for Limit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Limit::Steps(max_steps) => write!(f, "step budget of {} exhausted", max_steps),
+            Limit::Deadline => write!(f, "deadline exceeded"),
+            Limit::Cancelled => write!(f, "evaluation cancelled"),
+        }
+    }
+}
+
+/// The configuration in force on this thread, the steps taken under it and
+/// the stack in use since it was installed.
+struct Budget {
+    config: EvalConfig,
+    steps: u64,
+    /// The earlier of the configured deadline and the end of the timeout.
+    deadline: Option<Instant>,
+    stack: StackGuard,
+}
+
+thread_local! {
+    static BUDGET: RefCell<Budget> = RefCell::new(Budget {
+        config: EvalConfig::default(),
+        steps: 0,
+        deadline: None,
+        stack: StackGuard::new(DEFAULT_MAX_STACK),
+    });
+    static DEPTH: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Keeps a configuration in force on the current thread until dropped.
+pub(crate) struct Installed {
+    previous: Option<Budget>,
+}
+
+impl Drop for Installed {
+    fn drop(&mut self) {
+        if let Some(previous) = self.previous.take() {
+            BUDGET.replace(previous);
+        }
+    }
+}
+
+/// Puts `config` in force with a fresh step count, starting its timeout and
+/// measuring stack use from the caller's frame.
+pub(crate) fn install(config: &EvalConfig) -> Installed {
+    let timeout = config.timeout.map(|timeout| Instant::now() + timeout);
+    let budget = Budget {
+        config: config.clone(),
+        steps: 0,
+        deadline: config.deadline.into_iter().chain(timeout).min(),
+        stack: StackGuard::new(config.max_stack),
+    };
+    Installed {
+        previous: Some(BUDGET.replace(budget)),
+    }
+}
+
+/// Takes one step, failing if the step budget is spent, the deadline has
+/// passed or the run was cancelled.
+pub(crate) fn step() -> Result<(), EvaluatorError> {
+    take_steps(1)
+}
+
+/// Takes the steps for an operation that touches `size` elements, bytes or
+/// integer digits, one per [`SIZE_PER_STEP`]. Call it before doing the work,
+/// so that a run out of time or budget never starts it.
+pub(super) fn charge(size: usize) -> Result<(), EvaluatorError> {
+    match size as u64 / SIZE_PER_STEP {
+        0 => Ok(()),
+        steps => take_steps(steps),
+    }
+}
+
+fn take_steps(steps: u64) -> Result<(), EvaluatorError> {
+    BUDGET.with_borrow_mut(|budget| {
+        let config = &budget.config;
+        let total = budget.steps.saturating_add(steps);
+        if let Some(max_steps) = config.max_steps.filter(|max| total > *max) {
+            return Err(limit_exceeded(Limit::Steps(max_steps)));
+        }
+        // Poll whenever these steps include a multiple of the interval, which
+        // a large charge always does.
+        if budget.steps % CHECK_INTERVAL == 0
+            || budget.steps / CHECK_INTERVAL != (total - 1) / CHECK_INTERVAL
+        {
+            if config
+                .cancel
+                .as_ref()
+                .is_some_and(CancelHandle::is_cancelled)
+            {
+                return Err(limit_exceeded(Limit::Cancelled));
+            }
+            if budget
+                .deadline
+                .is_some_and(|deadline| Instant::now() >= deadline)
+            {
+                return Err(limit_exceeded(Limit::Deadline));
+            }
+        }
+        budget.steps = total;
+        Ok(())
+    })
+}
+
+fn limit_exceeded(limit: Limit) -> EvaluatorError {
+    EvaluatorErrorKind::LimitExceeded(limit).into()
+}
+
+/// Takes a step for one more level of expression nesting, failing if the
+/// step or stack budget is spent. Running out of stack reports the call
+/// depth reached as the limit.
+pub(super) fn enter() -> Result<(), EvaluatorError> {
+    step()?;
+    if BUDGET.with_borrow(|budget| budget.stack.exhausted()) {
+        return Err(EvaluatorErrorKind::RecursionLimit(DEPTH.get()).into());
+    }
+    Ok(())
+}
+
+/// Marks one function call as in progress until dropped.
+pub(crate) struct Depth;
+
+impl Drop for Depth {
+    fn drop(&mut self) {
+        DEPTH.set(DEPTH.get() - 1);
+    }
+}
+
+/// Enters a function call, failing if calls are already nested as deeply as
+/// the configuration allows.
+pub(crate) fn call() -> Result<Depth, EvaluatorError> {
+    let limit = BUDGET.with_borrow(|budget| budget.config.max_depth);
+    let depth = DEPTH.get();
+    if depth >= limit {
+        return Err(EvaluatorErrorKind::RecursionLimit(limit).into());
+    }
+    DEPTH.set(depth + 1);
+    Ok(Depth)
+}
@@ -1,16 +1,62 @@
+pub mod builtin;
 pub mod environment;
 pub mod error;
+pub mod limits;
 
+use std::cell::RefCell;
+use std::collections::BTreeMap;
 use std::rc::Rc;
 
-use self::environment::Env;
+use num_bigint::BigInt;
+use num_traits::{Signed, ToPrimitive, Zero};
+
+use self::environment::{Env, Environment};
 use self::error::*;
-use crate::ast::ast::{Expression, Literal, Node, Statement};
+use self::limits::EvalConfig;
+use crate::ast::ast::{Expression, ExpressionKind, Literal, Node, Statement, StatementKind};
 use crate::object::*;
+use crate::span::Span;
 use crate::token::token::Token;
 
 pub type EvaluatorResult = Result<Rc<Object>, EvaluatorError>;
 
+/// Why evaluation stopped short of a value: an error, or a `break` or
+/// `continue` leaving every expression around it until a loop catches it.
+enum Unwind {
+    Error(EvaluatorError),
+    Break,
+    Continue,
+}
+
+impl Unwind {
+    fn or_span(self, span: Span) -> Self {
+        match self {
+            Unwind::Error(err) => Unwind::Error(err.or_span(span)),
+            control => control,
+        }
+    }
+}
+
+impl From<EvaluatorError> for Unwind {
+    fn from(err: EvaluatorError) -> Self {
+        Unwind::Error(err)
+    }
+}
+
+/// The result of evaluating code that may contain `break` or `continue`.
+type Flow = Result<Rc<Object>, Unwind>;
+
+/// Ends the unwinding at a function body or the top of a program, where no
+/// loop is left to catch a `break` or `continue`. The parser rejects them
+/// there, so only hand-built syntax trees get the error.
+fn settle(flow: Flow) -> EvaluatorResult {
+    flow.map_err(|unwind| match unwind {
+        Unwind::Error(err) => err,
+        Unwind::Break => EvaluatorErrorKind::LoopControlOutsideLoop(Token::Break).into(),
+        Unwind::Continue => EvaluatorErrorKind::LoopControlOutsideLoop(Token::Continue).into(),
+    })
+}
+
 pub fn is_truthy(obj: &Object) -> bool {
     match obj {
         Object::Null => false,
@@ -20,28 +66,48 @@ pub fn is_truthy(obj: &Object) -> bool {
 }
 
 pub fn eval(node: Node, env: &Env) -> EvaluatorResult {
-    match node {
+    eval_with(node, env, &EvalConfig::default())
+}
+
+/// Evaluates `node` under the limits in `config`. The step budget starts
+/// afresh on every call.
+pub fn eval_with(node: Node, env: &Env, config: &EvalConfig) -> EvaluatorResult {
+    let _config = limits::install(config);
+    settle(match node {
         Node::Expr(expr) => eval_expression(&expr, env),
         Node::Statement(statement) => eval_statement(&statement, env),
         Node::Program(program) => eval_program(program, env),
-    }
+    })
+}
+
+fn eval_expression(expr: &Expression, env: &Env) -> Flow {
+    limits::enter()
+        .map_err(Unwind::from)
+        .and_then(|()| eval_expression_kind(expr, env))
+        .map_err(|err| err.or_span(expr.span))
 }
 
-pub fn eval_expression(expr: &Expression, env: &Env) -> EvaluatorResult {
-    match expr {
-        Expression::Identifier(id) => eval_identifier(id, env),
-        Expression::Lit(l) => eval_literal(l, env),
-        Expression::Prefix(op, expr) => {
+fn eval_expression_kind(expr: &Expression, env: &Env) -> Flow {
+    match &expr.kind {
+        ExpressionKind::Identifier(id) => eval_identifier(id, env).map_err(Unwind::from),
+        ExpressionKind::Lit(l) => eval_literal(l, env),
+        ExpressionKind::Prefix(op, expr) => {
             let right = eval_expression(expr, env)?;
-            eval_prefix_expression(op, &right)
+            eval_prefix_expression(op, &right).map_err(Unwind::from)
+        }
+        ExpressionKind::Infix(op @ (Token::And | Token::Or), left, right) => {
+            eval_logical_expression(op, left, right, env)
         }
-        Expression::Infix(op, left, right) => {
+        ExpressionKind::Infix(op, left, right) => {
             let left = eval_expression(left, env)?;
             let right = eval_expression(right, env)?;
-            eval_infix_expression(op, &left, &right)
+            eval_infix_expression(op, &left, &right).map_err(Unwind::from)
         }
-        Expression::Postfix(_, _) => todo!(),
-        Expression::If(condition, consequence, alternative) => {
+        ExpressionKind::Postfix(..) => {
+            let kind = EvaluatorErrorKind::UnsupportedExpression(expr.to_string());
+            Err(EvaluatorError::from(kind).into())
+        }
+        ExpressionKind::If(condition, consequence, alternative) => {
             let condition = eval_expression(condition, &Rc::clone(env))?;
 
             if is_truthy(&condition) {
@@ -53,12 +119,330 @@ pub fn eval_expression(expr: &Expression, env: &Env) -> EvaluatorResult {
                 }
             }
         }
-        Expression::Fn(_, _) => todo!(),
-        Expression::Call(_, _) => todo!(),
+        ExpressionKind::While(condition, body) => eval_while_expression(condition, body, env),
+        ExpressionKind::For(name, iterable, body) => {
+            let iterable = eval_expression(iterable, env)?;
+            eval_for_expression(name, &iterable, body, env)
+        }
+        ExpressionKind::Assign(op, target, value) => eval_assign_expression(op, target, value, env),
+        ExpressionKind::Range(start, end) => eval_range_expression(start, end, env),
+        ExpressionKind::Fn(params, body) => Ok(Rc::new(Object::Function(
+            params.clone(),
+            body.clone(),
+            Rc::clone(env),
+        ))),
+        ExpressionKind::Call(callee, args) => {
+            let func = eval_expression(callee, env)?;
+            let args = eval_expressions(args, env)?;
+            apply_function(&func, args, callee, expr.span).map_err(Unwind::from)
+        }
+        ExpressionKind::Index(left, index) => {
+            let left = eval_expression(left, env)?;
+            let index = eval_expression(index, env)?;
+            eval_index_expression(&left, &index).map_err(Unwind::from)
+        }
+    }
+}
+
+fn eval_assign_expression(op: &Token, target: &Expression, value: &Expression, env: &Env) -> Flow {
+    let (place, value) = match compound_operator(op) {
+        Some(op) => {
+            let place = Place::resolve(target, env)?;
+            let current = place.read(env)?;
+            let value = eval_expression(value, env)?;
+            (place, eval_infix_expression(&op, &current, &value)?)
+        }
+        None => {
+            let value = eval_expression(value, env)?;
+            (Place::resolve(target, env)?, value)
+        }
+    };
+    place.write(Rc::clone(&value), env)?;
+    Ok(value)
+}
+
+fn eval_range_expression(start: &Expression, end: &Expression, env: &Env) -> Flow {
+    let start = eval_expression(start, env)?;
+    let end = eval_expression(end, env)?;
+    match (start.as_ref(), end.as_ref()) {
+        (Object::Integer(start), Object::Integer(end)) => Ok(Rc::new(Object::Range(*start, *end))),
+        _ => Err(EvaluatorError::from(EvaluatorErrorKind::RangeBounds {
+            start: Operand::new(&start),
+            end: Operand::new(&end),
+        })
+        .into()),
+    }
+}
+
+/// Maps a compound assignment such as `+=` to the operator it applies.
+fn compound_operator(op: &Token) -> Option<Token> {
+    match op {
+        Token::PlusAssign => Some(Token::Plus),
+        Token::MinusAssign => Some(Token::Minus),
+        Token::AsteriskAssign => Some(Token::Asterisk),
+        Token::SlashAssign => Some(Token::Slash),
+        Token::PercentAssign => Some(Token::Percent),
+        _ => None,
+    }
+}
+
+/// An assignment target with its indices already evaluated, so that a
+/// compound assignment reads and writes the same element and evaluates each
+/// index expression only once.
+struct Place<'a> {
+    name: &'a str,
+    name_span: Span,
+    /// Each index applied to the variable, outermost first, with the span of
+    /// the indexing expression it came from.
+    indices: Vec<(Rc<Object>, Span)>,
+}
+
+impl<'a> Place<'a> {
+    fn resolve(target: &'a Expression, env: &Env) -> Result<Self, Unwind> {
+        match &target.kind {
+            ExpressionKind::Identifier(name) => Ok(Place {
+                name,
+                name_span: target.span,
+                indices: Vec::new(),
+            }),
+            ExpressionKind::Index(collection, index) => {
+                let mut place = Place::resolve(collection, env)?;
+                let index = eval_expression(index, env)?;
+                place.indices.push((index, target.span));
+                Ok(place)
+            }
+            _ => Err(
+                EvaluatorError::new(EvaluatorErrorKind::InvalidAssignmentTarget(
+                    target.to_string(),
+                ))
+                .or_span(target.span)
+                .into(),
+            ),
+        }
+    }
+
+    /// The collections the indices apply to: the variable's value, then the
+    /// element each index but the last selects.
+    fn containers(&self, env: &Env) -> Result<Vec<Rc<Object>>, EvaluatorError> {
+        let Some((_, inner)) = self.indices.split_last() else {
+            return Ok(Vec::new());
+        };
+        let mut containers = vec![self.root(env)?];
+        for (index, span) in inner {
+            let container = &containers[containers.len() - 1];
+            let element =
+                eval_index_expression(container, index).map_err(|err| err.or_span(*span))?;
+            containers.push(element);
+        }
+        Ok(containers)
+    }
+
+    fn read(&self, env: &Env) -> EvaluatorResult {
+        let containers = self.containers(env)?;
+        match (containers.last(), self.indices.last()) {
+            (Some(container), Some((index, span))) => {
+                eval_index_expression(container, index).map_err(|err| err.or_span(*span))
+            }
+            _ => self.root(env),
+        }
+    }
+
+    /// The variable's current value. Assignment never declares a variable,
+    /// so a missing one is reported the same way whether the assignment is
+    /// plain or compound.
+    fn root(&self, env: &Env) -> EvaluatorResult {
+        let value = env.borrow().get(self.name);
+        value.ok_or_else(|| self.undeclared())
+    }
+
+    fn undeclared(&self) -> EvaluatorError {
+        let kind = EvaluatorErrorKind::UndeclaredAssignment(self.name.to_string());
+        EvaluatorError::new(kind).or_span(self.name_span)
+    }
+
+    /// Stores `value` at this place. Collections are values, so assigning to
+    /// an element builds an updated copy of each enclosing collection, which
+    /// makes `grid[0][1] = v` work.
+    fn write(&self, value: Rc<Object>, env: &Env) -> Result<(), EvaluatorError> {
+        let containers = self.containers(env)?;
+        let mut value = value;
+        for (container, (index, span)) in containers.iter().zip(&self.indices).rev() {
+            value =
+                eval_index_assignment(container, index, value).map_err(|err| err.or_span(*span))?;
+        }
+        if env.borrow_mut().assign(self.name, value) {
+            Ok(())
+        } else {
+            Err(self.undeclared())
+        }
+    }
+}
+
+fn eval_index_assignment(
+    container: &Rc<Object>,
+    index: &Rc<Object>,
+    value: Rc<Object>,
+) -> EvaluatorResult {
+    match (container.as_ref(), index.as_ref()) {
+        (Object::Array(elements), Object::Integer(i)) => {
+            limits::charge(elements.len())?;
+            let mut elements = elements.clone();
+            match usize::try_from(*i).ok().and_then(|i| elements.get_mut(i)) {
+                Some(slot) => *slot = value,
+                None => {
+                    return Err(EvaluatorErrorKind::IndexOutOfRange {
+                        collection: Operand::new(container),
+                        index: Operand::new(index),
+                    }
+                    .into())
+                }
+            }
+            Ok(Rc::new(Object::Array(elements)))
+        }
+        (Object::Hash(pairs), _) => {
+            limits::charge(pairs.len())?;
+            let mut pairs = pairs.clone();
+            pairs.insert(hash_key(index)?, (Rc::clone(index), value));
+            Ok(Rc::new(Object::Hash(pairs)))
+        }
+        _ => Err(EvaluatorErrorKind::IndexAssignmentNotSupported {
+            collection: Operand::new(container),
+            index: Operand::new(index),
+        }
+        .into()),
+    }
+}
+
+pub(crate) fn eval_index_expression(left: &Rc<Object>, index: &Rc<Object>) -> EvaluatorResult {
+    match (left.as_ref(), index.as_ref()) {
+        (Object::Array(elements), Object::Integer(i)) => {
+            let element = usize::try_from(*i)
+                .ok()
+                .and_then(|i| elements.get(i))
+                .map(Rc::clone);
+            Ok(element.unwrap_or_else(|| Rc::new(Object::Null)))
+        }
+        (Object::Hash(pairs), _) => {
+            let key = hash_key(index)?;
+            match pairs.get(&key) {
+                Some((_, value)) => Ok(Rc::clone(value)),
+                None => Ok(Rc::new(Object::Null)),
+            }
+        }
+        _ => Err(EvaluatorErrorKind::IndexNotSupported {
+            collection: Operand::new(left),
+            index: Operand::new(index),
+        }
+        .into()),
+    }
+}
+
+fn eval_expressions(exprs: &[Expression], env: &Env) -> Result<Vec<Rc<Object>>, Unwind> {
+    exprs
+        .iter()
+        .map(|expr| eval_expression(expr, env))
+        .collect()
+}
+
+/// Calls `func` with `args`. Errors raised inside a function body carry a
+/// frame naming `callee` and pointing at `call_site`.
+fn apply_function(
+    func: &Rc<Object>,
+    args: Vec<Rc<Object>>,
+    callee: &Expression,
+    call_site: Span,
+) -> EvaluatorResult {
+    match func.as_ref() {
+        Object::Function(params, body, env) => {
+            if params.len() != args.len() {
+                return Err(EvaluatorErrorKind::WrongArgumentCount {
+                    expected: params.len(),
+                    got: args.len(),
+                }
+                .into());
+            }
+            let _depth = limits::call()?;
+            let mut enclosed = Environment::new_enclosed(env);
+            for (param, arg) in params.iter().zip(args) {
+                enclosed.set(param, arg);
+            }
+            let evaluated = settle(eval_block_statement(body, &Rc::new(RefCell::new(enclosed))))
+                .map_err(|err| err.with_frame(Frame::new(function_name(callee), call_site)))?;
+            match evaluated.as_ref() {
+                Object::ReturnValue(val) => Ok(Rc::clone(val)),
+                _ => Ok(evaluated),
+            }
+        }
+        Object::Builtin(_, builtin) => builtin(args),
+        _ => Err(EvaluatorErrorKind::NotAFunction(Operand::new(func)).into()),
+    }
+}
+
+/// The name a function is known by at a call site: the identifier it was
+/// called through, or `<anonymous>` for any other callee expression.
+fn function_name(callee: &Expression) -> String {
+    match &callee.kind {
+        ExpressionKind::Identifier(name) => name.clone(),
+        _ => "<anonymous>".to_string(),
+    }
+}
+
+/// Evaluates `&&` and `||`, skipping the right operand when the left one
+/// already decides the result.
+fn eval_logical_expression(op: &Token, left: &Expression, right: &Expression, env: &Env) -> Flow {
+    let left = is_truthy(&*eval_expression(left, env)?);
+    let result = match op {
+        Token::And if !left => false,
+        Token::Or if left => true,
+        _ => is_truthy(&*eval_expression(right, env)?),
+    };
+    Ok(Rc::new(Object::Boolean(result)))
+}
+
+fn eval_while_expression(condition: &Expression, body: &[Statement], env: &Env) -> Flow {
+    while is_truthy(&*eval_expression(condition, env)?) {
+        match eval_block_statement(body, env) {
+            Ok(result) if matches!(result.as_ref(), Object::ReturnValue(_)) => return Ok(result),
+            Ok(_) | Err(Unwind::Continue) => {}
+            Err(Unwind::Break) => break,
+            Err(err) => return Err(err),
+        }
+    }
+    Ok(Rc::new(Object::Null))
+}
+
+/// Runs `body` once per item of `iterable`. Each iteration binds `name` in a
+/// fresh scope, so closures created in the body capture their own item.
+fn eval_for_expression(name: &str, iterable: &Rc<Object>, body: &[Statement], env: &Env) -> Flow {
+    let items: Box<dyn Iterator<Item = Rc<Object>> + '_> = match iterable.as_ref() {
+        Object::Array(elements) => Box::new(elements.iter().cloned()),
+        Object::String(s) => Box::new(s.chars().map(|c| Rc::new(Object::String(c.to_string())))),
+        Object::Hash(pairs) => Box::new(
+            pairs
+                .values()
+                .map(|(key, value)| Rc::new(Object::Array(vec![Rc::clone(key), Rc::clone(value)]))),
+        ),
+        Object::Range(start, end) => Box::new((*start..*end).map(|i| Rc::new(Object::Integer(i)))),
+        _ => {
+            let kind = EvaluatorErrorKind::NotIterable(Operand::new(iterable));
+            return Err(EvaluatorError::from(kind).into());
+        }
+    };
+    for item in items {
+        limits::step()?;
+        let mut scope = Environment::new_enclosed(env);
+        scope.set(name, item);
+        match eval_block_statement(body, &Rc::new(RefCell::new(scope))) {
+            Ok(result) if matches!(result.as_ref(), Object::ReturnValue(_)) => return Ok(result),
+            Ok(_) | Err(Unwind::Continue) => {}
+            Err(Unwind::Break) => break,
+            Err(err) => return Err(err),
+        }
     }
+    Ok(Rc::new(Object::Null))
 }
 
-fn eval_block_statement(statements: &[Statement], env: &Env) -> EvaluatorResult {
+fn eval_block_statement(statements: &[Statement], env: &Env) -> Flow {
     let mut result = Rc::new(Object::Null);
 
     for statement in statements {
@@ -72,81 +456,243 @@ fn eval_block_statement(statements: &[Statement], env: &Env) -> EvaluatorResult
     Ok(result)
 }
 
-fn eval_infix_expression(op: &Token, left: &Object, right: &Object) -> EvaluatorResult {
-    match (left, right) {
-        (Object::Integer(l), Object::Integer(r)) => eval_integer_infix_expression(op, *l, *r),
-        (Object::Boolean(l), Object::Boolean(r)) => eval_boolean_infix_expression(op, *l, *r),
-        (Object::String(l), Object::String(r)) => {
-            eval_string_infix_expression(op, l.to_string(), r)
+pub(crate) fn eval_infix_expression(
+    op: &Token,
+    left: &Rc<Object>,
+    right: &Rc<Object>,
+) -> EvaluatorResult {
+    let operands = Operands { op, left, right };
+    match (left.as_ref(), right.as_ref()) {
+        (Object::Integer(l), Object::Integer(r)) => eval_integer_infix_expression(operands, *l, *r),
+        (Object::Float(l), Object::Float(r)) => eval_float_infix_expression(operands, *l, *r),
+        (Object::Integer(l), Object::Float(r)) => {
+            eval_float_infix_expression(operands, *l as f64, *r)
         }
-        _ => Err(EvaluatorError::new(format!(
-            "type mismatch: {} {} {}",
-            left, op, right
-        ))),
+        (Object::Float(l), Object::Integer(r)) => {
+            eval_float_infix_expression(operands, *l, *r as f64)
+        }
+        (Object::BigInteger(l), Object::BigInteger(r)) => {
+            eval_big_integer_infix_expression(operands, l, r)
+        }
+        (Object::Integer(l), Object::BigInteger(r)) => {
+            eval_big_integer_infix_expression(operands, &BigInt::from(*l), r)
+        }
+        (Object::BigInteger(l), Object::Integer(r)) => {
+            eval_big_integer_infix_expression(operands, l, &BigInt::from(*r))
+        }
+        (Object::BigInteger(l), Object::Float(r)) => {
+            eval_float_infix_expression(operands, big_to_f64(l), *r)
+        }
+        (Object::Float(l), Object::BigInteger(r)) => {
+            eval_float_infix_expression(operands, *l, big_to_f64(r))
+        }
+        (Object::Boolean(l), Object::Boolean(r)) => eval_boolean_infix_expression(operands, *l, *r),
+        (Object::String(l), Object::String(r)) => eval_string_infix_expression(operands, l, r),
+        _ => Err(EvaluatorErrorKind::TypeMismatch {
+            op: op.clone(),
+            left: Operand::new(left),
+            right: Operand::new(right),
+        }
+        .into()),
     }
 }
 
-fn eval_string_infix_expression(op: &Token, l: String, r: &str) -> EvaluatorResult {
-    let result = match op {
+/// An infix operator and its operands as evaluated, for describing them in
+/// errors.
+#[derive(Clone, Copy)]
+struct Operands<'a> {
+    op: &'a Token,
+    left: &'a Rc<Object>,
+    right: &'a Rc<Object>,
+}
+
+impl Operands<'_> {
+    fn unknown_operator(self) -> EvaluatorError {
+        EvaluatorErrorKind::UnknownInfixOperator {
+            op: self.op.clone(),
+            left: Operand::new(self.left),
+            right: Operand::new(self.right),
+        }
+        .into()
+    }
+
+    fn division_by_zero(self) -> EvaluatorError {
+        EvaluatorErrorKind::DivisionByZero {
+            op: self.op.clone(),
+            left: Operand::new(self.left),
+            right: Operand::new(self.right),
+        }
+        .into()
+    }
+
+    fn exponent_error(self, negative: bool) -> EvaluatorError {
+        let (base, exponent) = (Operand::new(self.left), Operand::new(self.right));
+        if negative {
+            EvaluatorErrorKind::NegativeExponent { base, exponent }.into()
+        } else {
+            EvaluatorErrorKind::ExponentTooLarge { base, exponent }.into()
+        }
+    }
+}
+
+fn eval_string_infix_expression(operands: Operands, l: &str, r: &str) -> EvaluatorResult {
+    limits::charge(l.len() + r.len())?;
+    let result = match operands.op {
         Token::Eq => Object::Boolean(l == r),
         Token::NotEq => Object::Boolean(l != r),
-        Token::Plus => Object::String(l + r),
-        op => {
-            return Err(EvaluatorError::new(format!(
-                "unknown operator: {} {} {}",
-                l, op, r
-            )))
-        }
+        Token::Lt => Object::Boolean(l < r),
+        Token::Gt => Object::Boolean(l > r),
+        Token::LtEq => Object::Boolean(l <= r),
+        Token::GtEq => Object::Boolean(l >= r),
+        Token::Plus => Object::String([l, r].concat()),
+        _ => return Err(operands.unknown_operator()),
     };
     Ok(Rc::new(result))
 }
 
-fn eval_boolean_infix_expression(op: &Token, l: bool, r: bool) -> EvaluatorResult {
-    match op {
+fn eval_boolean_infix_expression(operands: Operands, l: bool, r: bool) -> EvaluatorResult {
+    match operands.op {
         Token::Eq => Ok(Rc::new(Object::Boolean(l == r))),
         Token::NotEq => Ok(Rc::new(Object::Boolean(l != r))),
-        _ => Err(EvaluatorError::new(format!(
-            "unknown operator: {} {} {}",
-            l, op, r
-        ))),
+        _ => Err(operands.unknown_operator()),
     }
 }
 
-fn eval_integer_infix_expression(op: &Token, l: i32, r: i32) -> EvaluatorResult {
-    let left_val = Rc::new(Object::Integer(l));
-    let right_val = Rc::new(Object::Integer(r));
+fn eval_integer_infix_expression(operands: Operands, l: i64, r: i64) -> EvaluatorResult {
+    let result = match operands.op {
+        Token::Plus => l.checked_add(r),
+        Token::Minus => l.checked_sub(r),
+        Token::Asterisk => l.checked_mul(r),
+        Token::Slash | Token::Percent if r == 0 => return Err(operands.division_by_zero()),
+        Token::Slash => l.checked_div(r),
+        Token::Percent => l.checked_rem(r),
+        Token::Power => match u32::try_from(r) {
+            Ok(exponent) => l.checked_pow(exponent),
+            Err(_) => return Err(operands.exponent_error(r < 0)),
+        },
+        Token::Lt => return Ok(Rc::new(Object::Boolean(l < r))),
+        Token::Gt => return Ok(Rc::new(Object::Boolean(l > r))),
+        Token::LtEq => return Ok(Rc::new(Object::Boolean(l <= r))),
+        Token::GtEq => return Ok(Rc::new(Object::Boolean(l >= r))),
+        Token::Eq => return Ok(Rc::new(Object::Boolean(l == r))),
+        Token::NotEq => return Ok(Rc::new(Object::Boolean(l != r))),
+        _ => return Err(operands.unknown_operator()),
+    };
+    match result {
+        Some(i) => Ok(Rc::new(Object::Integer(i))),
+        None => eval_big_integer_infix_expression(operands, &BigInt::from(l), &BigInt::from(r)),
+    }
+}
 
-    match op {
-        Token::Plus => Ok(Rc::new(Object::Integer(l + r))),
-        Token::Minus => Ok(Rc::new(Object::Integer(l - r))),
-        Token::Asterisk => Ok(Rc::new(Object::Integer(l * r))),
-        Token::Slash => Ok(Rc::new(Object::Integer(l / r))),
-        Token::Lt => Ok(Rc::new(Object::Boolean(l < r))),
-        Token::Gt => Ok(Rc::new(Object::Boolean(l > r))),
-        Token::Eq => Ok(Rc::new(Object::Boolean(l == r))),
-        Token::NotEq => Ok(Rc::new(Object::Boolean(l != r))),
-        _ => Err(EvaluatorError::new(format!(
-            "unknown operator: {} {} {}",
-            left_val, op, right_val
-        ))),
+/// Integers are kept below this many bits, so that a single `*` or `**`
+/// cannot exhaust memory or run for minutes.
+pub const MAX_INTEGER_BITS: u64 = 1 << 18;
+
+/// The slow path for integers that do not fit in an `i64`. Results that fit
+/// again are narrowed back to `Object::Integer`.
+fn eval_big_integer_infix_expression(
+    operands: Operands,
+    l: &BigInt,
+    r: &BigInt,
+) -> EvaluatorResult {
+    let op = operands.op;
+    charge_digits(l.bits().max(r.bits()))?;
+    let result = match op {
+        Token::Plus => l + r,
+        Token::Minus => l - r,
+        Token::Asterisk => {
+            check_integer_size(op, l.bits() + r.bits())?;
+            charge_digits(l.bits() + r.bits())?;
+            l * r
+        }
+        Token::Slash | Token::Percent if r.is_zero() => return Err(operands.division_by_zero()),
+        Token::Slash => l / r,
+        Token::Percent => l % r,
+        Token::Power => match r.to_u32() {
+            Some(exponent) => {
+                // |l| has at least `bits - 1` bits below its leading one, so
+                // this underestimates the size of the result. Bases 0 and ±1
+                // never grow.
+                let bits = (l.bits().max(1) - 1) * u64::from(exponent);
+                check_integer_size(op, bits)?;
+                charge_digits(bits)?;
+                l.pow(exponent)
+            }
+            None => return Err(operands.exponent_error(r.is_negative())),
+        },
+        Token::Lt => return Ok(Rc::new(Object::Boolean(l < r))),
+        Token::Gt => return Ok(Rc::new(Object::Boolean(l > r))),
+        Token::LtEq => return Ok(Rc::new(Object::Boolean(l <= r))),
+        Token::GtEq => return Ok(Rc::new(Object::Boolean(l >= r))),
+        Token::Eq => return Ok(Rc::new(Object::Boolean(l == r))),
+        Token::NotEq => return Ok(Rc::new(Object::Boolean(l != r))),
+        _ => return Err(operands.unknown_operator()),
+    };
+    Ok(Rc::new(Object::from(result)))
+}
+
+/// Rejects a result that would need more than [`MAX_INTEGER_BITS`] bits.
+fn check_integer_size(op: &Token, bits: u64) -> Result<(), EvaluatorError> {
+    if bits > MAX_INTEGER_BITS {
+        let op = op.clone();
+        return Err(EvaluatorErrorKind::IntegerTooLarge { op, bits }.into());
     }
+    Ok(())
+}
+
+/// Charges an operation on integers of `bits` bits by their 64-bit digits.
+fn charge_digits(bits: u64) -> Result<(), EvaluatorError> {
+    limits::charge(usize::try_from(bits / 64).unwrap_or(usize::MAX))
+}
+
+fn big_to_f64(b: &BigInt) -> f64 {
+    b.to_f64().unwrap_or(f64::NAN)
+}
+
+fn eval_float_infix_expression(operands: Operands, l: f64, r: f64) -> EvaluatorResult {
+    let result = match operands.op {
+        Token::Plus => Object::Float(l + r),
+        Token::Minus => Object::Float(l - r),
+        Token::Asterisk => Object::Float(l * r),
+        Token::Slash => Object::Float(l / r),
+        Token::Percent => Object::Float(l % r),
+        Token::Power => Object::Float(l.powf(r)),
+        Token::Lt => Object::Boolean(l < r),
+        Token::Gt => Object::Boolean(l > r),
+        Token::LtEq => Object::Boolean(l <= r),
+        Token::GtEq => Object::Boolean(l >= r),
+        Token::Eq => Object::Boolean(l == r),
+        Token::NotEq => Object::Boolean(l != r),
+        _ => return Err(operands.unknown_operator()),
+    };
+    Ok(Rc::new(result))
 }
 
-fn eval_prefix_expression(op: &Token, expr: &Rc<Object>) -> EvaluatorResult {
+pub(crate) fn eval_prefix_expression(op: &Token, expr: &Rc<Object>) -> EvaluatorResult {
     match op {
         Token::Bang => eval_bang_operator_expression(expr),
         Token::Minus => eval_minus_prefix_operator_expression(expr),
-        _ => Err(EvaluatorError::new(format!(
-            "unknown operator: {}{}",
-            op, expr
-        ))),
+        _ => Err(EvaluatorErrorKind::UnknownPrefixOperator {
+            op: op.clone(),
+            operand: Operand::new(expr),
+        }
+        .into()),
     }
 }
 
 fn eval_minus_prefix_operator_expression(expr: &Rc<Object>) -> EvaluatorResult {
     match **expr {
-        Object::Integer(i) => Ok(Rc::new(Object::Integer(-i))),
-        _ => Err(EvaluatorError::new(format!("unknown operator: -{}", expr))),
+        Object::Integer(i) => match i.checked_neg() {
+            Some(negated) => Ok(Rc::new(Object::Integer(negated))),
+            None => Ok(Rc::new(Object::from(-BigInt::from(i)))),
+        },
+        Object::BigInteger(ref b) => Ok(Rc::new(Object::from(-b))),
+        Object::Float(x) => Ok(Rc::new(Object::Float(-x))),
+        _ => Err(EvaluatorErrorKind::UnknownPrefixOperator {
+            op: Token::Minus,
+            operand: Operand::new(expr),
+        }
+        .into()),
     }
 }
 
@@ -158,43 +704,72 @@ fn eval_bang_operator_expression(expr: &Rc<Object>) -> EvaluatorResult {
     }
 }
 
-fn eval_literal(lit: &Literal, _env: &Env) -> EvaluatorResult {
+fn eval_literal(lit: &Literal, env: &Env) -> Flow {
     match lit {
         Literal::Int(i) => Ok(Rc::new(Object::Integer(*i))),
+        Literal::BigInt(b) => Ok(Rc::new(Object::from(b.clone()))),
+        Literal::Float(x) => Ok(Rc::new(Object::Float(*x))),
         Literal::String(_) => Ok(Rc::new(Object::String(lit.to_string()))),
         Literal::Bool(bool) => Ok(Rc::new(Object::Boolean(*bool))),
-        Literal::Array(_) => todo!(),
-        Literal::Hash(_) => todo!(),
+        Literal::Array(elements) => {
+            let elements = eval_expressions(elements, env)?;
+            Ok(Rc::new(Object::Array(elements)))
+        }
+        Literal::Hash(pairs) => eval_hash_literal(pairs, env),
+    }
+}
+
+fn eval_hash_literal(pairs: &[(Expression, Expression)], env: &Env) -> Flow {
+    let mut hash = BTreeMap::new();
+    for (key_expr, value_expr) in pairs {
+        let key = eval_expression(key_expr, env)?;
+        let hashed = hash_key(&key)?;
+        let value = eval_expression(value_expr, env)?;
+        hash.insert(hashed, (key, value));
     }
+    Ok(Rc::new(Object::Hash(hash)))
+}
+
+pub(crate) fn hash_key(obj: &Object) -> Result<HashKey, EvaluatorError> {
+    obj.hash_key()
+        .ok_or_else(|| EvaluatorErrorKind::UnusableHashKey(obj.type_name()).into())
 }
 
 pub fn eval_identifier(id: &str, env: &Env) -> Result<Rc<Object>, EvaluatorError> {
-    match env.borrow().get(id) {
+    if let Some(obj) = env.borrow().get(id) {
+        return Ok(obj);
+    }
+    match builtin::lookup(id) {
         Some(obj) => Ok(obj),
-        None => Err(EvaluatorError::new(format!("identifier not found: {}", id))),
+        None => Err(EvaluatorErrorKind::UnknownIdentifier(id.to_string()).into()),
     }
 }
 
-pub fn eval_statement(statement: &Statement, env: &Env) -> EvaluatorResult {
-    match statement {
-        Statement::Let(_, _) => todo!(),
-        Statement::Return(expr) => {
+fn eval_statement(statement: &Statement, env: &Env) -> Flow {
+    match &statement.kind {
+        StatementKind::Let(id, expr) => {
+            let val = eval_expression(expr, env)?;
+            env.borrow_mut().set(id, val);
+            Ok(Rc::new(Object::Null))
+        }
+        StatementKind::Return(expr) => {
             let val = eval_expression(expr, env)?;
             Ok(Rc::new(Object::ReturnValue(val)))
         }
-        Statement::Expr(expr) => eval_expression(expr, env),
+        StatementKind::Expr(expr) => eval_expression(expr, env),
+        StatementKind::Break => Err(Unwind::Break),
+        StatementKind::Continue => Err(Unwind::Continue),
     }
 }
 
-pub fn eval_program(program: Vec<Statement>, env: &Env) -> EvaluatorResult {
+fn eval_program(program: Vec<Statement>, env: &Env) -> Flow {
     let mut result = Rc::new(Object::Null);
 
     for statement in program {
         result = eval_statement(&statement, &Rc::clone(env))?;
 
-        match result.as_ref() {
-            Object::ReturnValue(_) => return Ok(result),
-            _ => (),
+        if let Object::ReturnValue(_) = result.as_ref() {
+            return Ok(result);
         }
     }
 
@@ -205,12 +780,20 @@ pub fn eval_program(program: Vec<Statement>, env: &Env) -> EvaluatorResult {
 mod test {
     use std::cell::RefCell;
     use std::rc::Rc;
+    use std::thread;
+    use std::time::Duration;
 
     use crate::parser::parser::parse;
+    use crate::runner::{Backend, Session};
+    use crate::span::Span;
+    use crate::token::token::Token;
 
     use super::environment::Env;
 
-    use super::eval;
+    use super::error::{EvaluatorErrorKind, MAX_OPERAND_LEN};
+    use super::limits::{CancelHandle, EvalConfig, Limit};
+    use super::{eval, eval_with};
+    use crate::ast::ast::{Expression, ExpressionKind, Node, Statement, StatementKind};
 
     fn apply_test(test_case: &[(&str, &str)]) {
         let env: Env = Rc::new(RefCell::new(Default::default()));
@@ -226,6 +809,21 @@ mod test {
         }
     }
 
+    /// Runs `test_case` through the evaluator and then through the bytecode
+    /// VM, which must give the same results.
+    fn apply_test_on_both_backends(test_case: &[(&str, &str)]) {
+        apply_test(test_case);
+
+        let mut session = Session::new(Backend::Vm);
+        for (input, expected) in test_case {
+            let actual = match session.run(input) {
+                Ok(value) => value.to_string(),
+                Err(err) => err.to_string(),
+            };
+            assert_eq!(expected, &actual, "VM backend on {:?}", input);
+        }
+    }
+
     #[test]
     fn test_integer_expression() {
         let test_case = vec![
@@ -237,7 +835,7 @@ mod test {
             ("10+20", "30"),
         ];
 
-        apply_test(&test_case);
+        apply_test_on_both_backends(&test_case);
     }
 
     #[test]
@@ -249,7 +847,156 @@ mod test {
             ("1<1", "false"),
         ];
 
-        apply_test(&test_case)
+        apply_test_on_both_backends(&test_case)
+    }
+
+    #[test]
+    fn test_comparison_and_arithmetic_operators() {
+        let test_case = vec![
+            ("1 <= 2", "true"),
+            ("2 <= 2", "true"),
+            ("3 <= 2", "false"),
+            ("1 >= 2", "false"),
+            ("2 >= 2", "true"),
+            ("7 % 3", "1"),
+            ("-7 % 3", "-1"),
+            ("2 ** 10", "1024"),
+            ("2 ** 3 ** 2", "512"),
+            ("-2 ** 2", "-4"),
+            ("(-2) ** 2", "4"),
+            ("5 ** 0", "1"),
+            ("2 ** -1", "negative exponent: 2 ** -1"),
+            ("1 + 10 % 4 * 2", "5"),
+        ];
+
+        apply_test_on_both_backends(&test_case)
+    }
+
+    #[test]
+    fn test_checked_integer_arithmetic() {
+        let test_case = vec![
+            ("1 / 0", "division by zero: 1 / 0"),
+            ("5 % 0", "division by zero: 5 % 0"),
+            ("2 ** -1", "negative exponent: 2 ** -1"),
+            ("2 ** 4294967296", "exponent too large: 2 ** 4294967296"),
+            ("-(-9223372036854775807 - 1)", "9223372036854775808"),
+            ("(-9223372036854775807 - 1) % -1", "0"),
+            ("2147483647 + 1", "2147483648"),
+            ("-9223372036854775807 - 1", "-9223372036854775808"),
+            ("let f = fn(x) { 10 / x }; f(0)", "division by zero: 10 / 0"),
+            ("1 + 1", "2"),
+        ];
+
+        apply_test_on_both_backends(&test_case)
+    }
+
+    #[test]
+    fn test_big_integer_arithmetic() {
+        let test_case = vec![
+            ("9223372036854775807 + 1", "9223372036854775808"),
+            ("-9223372036854775807 - 2", "-9223372036854775809"),
+            ("4294967296 * 4294967296", "18446744073709551616"),
+            ("2 ** 63", "9223372036854775808"),
+            ("(-9223372036854775807 - 1) / -1", "9223372036854775808"),
+            ("let big = 2 ** 64; big", "18446744073709551616"),
+            ("big - 2 ** 64 + 1", "1"),
+            ("big / 2 ** 60", "16"),
+            ("big % 1000", "616"),
+            ("-big", "-18446744073709551616"),
+            ("big > 9223372036854775807", "true"),
+            ("big == 2 ** 64", "true"),
+            ("big != 2 ** 64 + 1", "true"),
+            ("-big < -1", "true"),
+            ("big / 0", "division by zero: 18446744073709551616 / 0"),
+            ("big * 0.5", "9.223372036854776e18"),
+            ("big + true", "type mismatch: 18446744073709551616 + true"),
+            ("{big: 1}[2 ** 64]", "1"),
+            ("99999999999999999999", "99999999999999999999"),
+            ("18446744073709551616 == big", "true"),
+            ("-9223372036854775808", "-9223372036854775808"),
+            ("-9223372036854775808 - 1", "-9223372036854775809"),
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(30)",
+                "265252859812191058636308480000000",
+            ),
+        ];
+
+        apply_test_on_both_backends(&test_case)
+    }
+
+    #[test]
+    fn test_integer_size_limit() {
+        let test_case = [
+            (
+                "3 ** 4000000000",
+                "integer too large: `**` would produce about 4000000000 bits, the limit is 262144",
+            ),
+            (
+                "2 ** 4000000000",
+                "integer too large: `**` would produce about 4000000000 bits, the limit is 262144",
+            ),
+            ("(-1) ** 4000000001", "-1"),
+            ("0 ** 4000000000", "0"),
+            ("1 ** 4000000000", "1"),
+            ("let big = 2 ** 200000; big == 2 ** 200000", "true"),
+            (
+                "big * big",
+                "integer too large: `*` would produce about 400002 bits, the limit is 262144",
+            ),
+        ];
+        apply_test(&test_case);
+
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let err = eval(parse("10 ** 100000").expect("parse"), &env).expect_err("too large");
+        assert!(matches!(
+            err.kind(),
+            EvaluatorErrorKind::IntegerTooLarge {
+                op: Token::Power,
+                bits: 300000
+            }
+        ));
+        assert_eq!(err.code(), "E0224");
+    }
+
+    #[test]
+    fn test_float_expression() {
+        let test_case = vec![
+            ("3.14", "3.14"),
+            ("1e-9", "1e-9"),
+            ("2.5e3", "2500.0"),
+            ("0x1F", "31"),
+            ("-1.5", "-1.5"),
+            ("1.5 + 1.5", "3.0"),
+            ("0.1 + 0.2", "0.30000000000000004"),
+            ("1 + 0.5", "1.5"),
+            ("0.5 * 4", "2.0"),
+            ("7 / 2.0", "3.5"),
+            ("7.5 % 2", "1.5"),
+            ("2 ** 0.5", "1.4142135623730951"),
+            ("1.0 / 0", "inf"),
+            ("1 == 1.0", "true"),
+            ("2.5 > 2", "true"),
+            ("1.5 <= 1.25", "false"),
+            ("1.5 + true", "type mismatch: 1.5 + true"),
+            ("{1.5: 1}", "unusable as hash key: FLOAT"),
+        ];
+
+        apply_test_on_both_backends(&test_case)
+    }
+
+    #[test]
+    fn test_string_comparison() {
+        let test_case = vec![
+            (r#""apple" < "banana""#, "true"),
+            (r#""apple" > "banana""#, "false"),
+            (r#""apple" <= "apple""#, "true"),
+            (r#""b" >= "abc""#, "true"),
+            (r#""Z" < "a""#, "true"),
+            ("\"\" < \"a\"", "true"),
+            (r#""a" * "b""#, "unknown operator: a * b"),
+        ];
+
+        apply_test_on_both_backends(&test_case)
     }
 
     #[test]
@@ -264,7 +1011,26 @@ mod test {
             ("if (1 < 2) { 10 } else { 20 }", "10"),
         ];
 
-        apply_test(&test_case)
+        apply_test_on_both_backends(&test_case)
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let test_case = vec![
+            ("true && true", "true"),
+            ("true && false", "false"),
+            ("false || true", "true"),
+            ("false || false", "false"),
+            ("1 && \"\"", "true"),
+            ("if (false) { 1 } || 0", "true"),
+            ("1 < 2 && 2 < 3", "true"),
+            ("false && 1 + true", "false"),
+            ("true || undefined", "true"),
+            ("false && undefined", "false"),
+            ("true && 1 + true", "type mismatch: 1 + true"),
+        ];
+
+        apply_test_on_both_backends(&test_case)
     }
 
     #[test]
@@ -278,6 +1044,208 @@ mod test {
             ("!!5", "true"),
         ];
 
+        apply_test_on_both_backends(&test_case)
+    }
+
+    #[test]
+    fn test_while_expression() {
+        let test_case = vec![
+            ("while (false) { 1 }", "null"),
+            ("let i = 0; while (i < 5) { let i = i + 1; }; i", "5"),
+            (
+                "let i = 0; while (true) { let i = i + 1; if (i == 3) { break; } }; i",
+                "3",
+            ),
+            (
+                "let i = 0; let odd = 0; while (i < 10) { let i = i + 1; if (i % 2 == 0) { continue; } let odd = odd + 1; }; odd",
+                "5",
+            ),
+            (
+                "let f = fn() { while (true) { return 7; } }; f()",
+                "7",
+            ),
+            (
+                "let n = 0; while (n < 3) { let n = n + 1; while (true) { break; } }; n",
+                "3",
+            ),
+            ("while (x) { 1 }", "identifier not found: x"),
+        ];
+
+        apply_test(&test_case)
+    }
+
+    #[test]
+    fn test_for_expression() {
+        let test_case = vec![
+            ("0..3", "0..3"),
+            ("1 + 1..2 * 3", "2..6"),
+            ("for (x in [1, 2, 3]) { x }", "null"),
+            (
+                "let find = fn(xs, target) { for (x in xs) { if (x == target) { return true; } } false }",
+                "null",
+            ),
+            ("find([1, 2, 3], 2)", "true"),
+            ("find([1, 2, 3], 4)", "false"),
+            ("find(0..10, 9)", "true"),
+            ("find(0..10, 10)", "false"),
+            (r#"find("héllo", "é")"#, "true"),
+            (
+                r#"let keyOf = fn(h, v) { for (pair in h) { if (pair[1] == v) { return pair[0]; } } }; keyOf({"a": 1, "b": 2}, 2)"#,
+                "b",
+            ),
+            (
+                "fn() { for (i in 0..10) { if (i < 3) { continue; } return i; } }()",
+                "3",
+            ),
+            ("fn() { for (i in 0..10) { break; return i; } }()", "null"),
+            ("for (i in 5..0) { i }", "null"),
+            ("for (y in [1]) { y }; y", "identifier not found: y"),
+            ("for (x in 5) { x }", "not iterable: 5"),
+            ("true..1", "range bounds must be integers: true..1"),
+        ];
+
+        apply_test(&test_case)
+    }
+
+    #[test]
+    fn test_loop_control_inside_expressions() {
+        let test_case = vec![
+            (
+                "let i = 0; let seen = []; while (i < 3) { i += 1; let x = if (true) { break; }; seen = push(seen, x); }; [i, seen]",
+                "[1, []]",
+            ),
+            (
+                "let r = 0; for (i in 0..3) { r = 1 + if (true) { break; }; }; r",
+                "0",
+            ),
+            (
+                "let r = 0; for (i in 0..5) { r = r + if (i % 2 == 0) { continue; } else { i }; }; r",
+                "4",
+            ),
+            (
+                "let n = 0; while (n < 3) { n += 1; puts(if (true) { continue; }); }; n",
+                "3",
+            ),
+            (
+                "let calls = 0; let f = fn(x) { calls += 1; x }; for (i in 0..3) { f(if (i == 1) { break; } else { i }); }; calls",
+                "1",
+            ),
+            (
+                "let out = []; for (i in 0..3) { out = push(out, [i, if (i == 1) { continue; }]); }; len(out)",
+                "2",
+            ),
+        ];
+
+        apply_test(&test_case)
+    }
+
+    #[test]
+    fn test_loop_control_outside_loop() {
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let statement = Statement::new(StatementKind::Break, Span::new(0, 5, 1, 1));
+        let err = eval(Node::Statement(statement), &env).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            EvaluatorErrorKind::LoopControlOutsideLoop(Token::Break)
+        ));
+        assert_eq!(err.code(), "E0225");
+        assert_eq!(err.to_string(), "`break` outside of a loop");
+    }
+
+    #[test]
+    fn test_unsupported_expression() {
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let operand = Expression::new(
+            ExpressionKind::Identifier("x".to_string()),
+            Span::new(0, 1, 1, 1),
+        );
+        let postfix = ExpressionKind::Postfix(Token::Bang, Box::new(operand));
+        let expr = Expression::new(postfix, Span::new(0, 2, 1, 1));
+        let err = eval(Node::Expr(expr), &env).unwrap_err();
+        assert!(matches!(
+            err.kind(),
+            EvaluatorErrorKind::UnsupportedExpression(expr) if expr == "(x!)"
+        ));
+        assert_eq!(err.code(), "E0226");
+        assert_eq!(err.span(), Some(Span::new(0, 2, 1, 1)));
+    }
+
+    #[test]
+    fn test_assign_expression() {
+        let test_case = vec![
+            ("let x = 1", "null"),
+            ("x = 2", "2"),
+            ("x", "2"),
+            ("x += 3", "5"),
+            ("x -= 1", "4"),
+            ("x *= 3", "12"),
+            ("x /= 5", "2"),
+            ("x %= 2", "0"),
+            ("let a = 0; let b = 0; a = b = 7; a + b", "14"),
+            ("y = 1", "assignment to undeclared identifier: y"),
+            ("y += 1", "assignment to undeclared identifier: y"),
+            ("y[0] += 1", "assignment to undeclared identifier: y"),
+            ("len += 1", "assignment to undeclared identifier: len"),
+            (
+                "let calls = 0; let one = fn() { calls += 1; 1 }; y += one()",
+                "assignment to undeclared identifier: y",
+            ),
+            ("calls", "0"),
+            (
+                "let counter = 0; let inc = fn() { counter += 1 }; inc(); inc(); counter",
+                "2",
+            ),
+            (
+                "let s = 1; let f = fn() { let s = 10; s = 20; s }; f() + s",
+                "21",
+            ),
+            (
+                "let i = 0; let total = 0; while (i < 5) { i += 1; total += i; }; total",
+                "15",
+            ),
+            (
+                "let fs = []; for (i in 0..3) { fs = push(fs, fn() { i }); }; fs[0]() + fs[1]() * 10 + fs[2]() * 100",
+                "210",
+            ),
+        ];
+
+        apply_test(&test_case)
+    }
+
+    #[test]
+    fn test_index_assignment() {
+        let test_case = vec![
+            ("let arr = [1, 2, 3]; arr[0] = 10; arr", "[10, 2, 3]"),
+            ("arr[1] += 5; arr", "[10, 7, 3]"),
+            ("arr[3] = 1", "index out of range: [10, 7, 3][3]"),
+            ("let copy = arr; copy[0] = 0; arr[0]", "10"),
+            (
+                r#"let h = {"a": 1}; h["b"] = 2; h["a"] = 0; h"#,
+                "{a: 0, b: 2}",
+            ),
+            (
+                "let grid = [[1, 2], [3, 4]]; grid[1][0] = 9; grid",
+                "[[1, 2], [9, 4]]",
+            ),
+            (
+                "let n = 5; n[0] = 1",
+                "index assignment not supported: 5[0]",
+            ),
+            ("h[[1]] = 1", "unusable as hash key: ARRAY"),
+            (
+                "let n = 0; let next = fn() { n += 1; n - 1 }; let a = [10, 20, 30]; a[next()] += 1; [n, a]",
+                "[1, [11, 20, 30]]",
+            ),
+            (
+                "let n = 0; let next = fn() { n += 1; n - 1 }; let g = [[1, 2], [3, 4]]; g[next()][next()] *= 5; [n, g]",
+                "[2, [[1, 10], [3, 4]]]",
+            ),
+            (
+                "let n = 0; let next = fn() { n += 1; n - 1 }; let g = [[1, 2], [3, 4]]; g[next()][0] = 7; [n, g]",
+                "[1, [[7, 2], [3, 4]]]",
+            ),
+        ];
+
         apply_test(&test_case)
     }
 
@@ -290,7 +1258,7 @@ mod test {
             ("9; return 2 * 5; 9;", "10"),
         ];
 
-        apply_test(&test_case)
+        apply_test_on_both_backends(&test_case)
     }
 
     #[test]
@@ -306,6 +1274,466 @@ mod test {
             ),
             ("5; true + false; 5", "unknown operator: true + false"),
             (r#""Hello" - "World""#, "unknown operator: Hello - World"),
+            ("foobar", "identifier not found: foobar"),
+        ];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_let_statements() {
+        let test_case = [
+            ("let a = 5; a;", "5"),
+            ("let a = 5 * 5; a;", "25"),
+            ("let a = 5; let b = a; b;", "5"),
+            ("let a = 5; let b = a; let c = a + b + 5; c;", "15"),
+            ("let a = 5; let a = a * 2; a;", "10"),
+            ("let x = 1;", "null"),
+        ];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_function_object() {
+        let test_case = [("fn(x) { x + 2; };", "fn(x) {...}")];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_function_application() {
+        let test_case = [
+            ("let identity = fn(x) { x; }; identity(5);", "5"),
+            ("let identity = fn(x) { return x; }; identity(5);", "5"),
+            ("let double = fn(x) { x * 2; }; double(5);", "10"),
+            ("let add = fn(x, y) { x + y; }; add(5, 5);", "10"),
+            (
+                "let add = fn(x, y) { x + y; }; add(5 + 5, add(5, 5));",
+                "20",
+            ),
+            ("fn(x) { x; }(5)", "5"),
+            ("let f = fn() { return 1; 2; }; f(); 3;", "3"),
+        ];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_closures() {
+        let test_case = [
+            (
+                "let newAdder = fn(x) { fn(y) { x + y }; }; let addTwo = newAdder(2); addTwo(2);",
+                "4",
+            ),
+            (
+                "let fact = fn(n) { if (n < 2) { 1 } else { n * fact(n - 1) } }; fact(5);",
+                "120",
+            ),
+        ];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_function_errors() {
+        let test_case = [
+            (
+                "let add = fn(x, y) { x + y; }; add(1);",
+                "wrong number of arguments: expected 2, got 1",
+            ),
+            ("5(1)", "not a function: 5"),
+            ("let f = fn() { 1 + true }; f();", "type mismatch: 1 + true"),
+        ];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_array_literals() {
+        let test_case = [
+            ("[]", "[]"),
+            ("[1, 2 * 2, 3 + 3]", "[1, 4, 6]"),
+            ("[1, [true, fn(x) { x }]]", "[1, [true, fn(x) {...}]]"),
+        ];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_array_index_expressions() {
+        let test_case = [
+            ("[1, 2, 3][0]", "1"),
+            ("[1, 2, 3][1]", "2"),
+            ("[1, 2, 3][2]", "3"),
+            ("let i = 0; [1][i];", "1"),
+            ("[1, 2, 3][1 + 1];", "3"),
+            ("let myArray = [1, 2, 3]; myArray[2];", "3"),
+            (
+                "let myArray = [1, 2, 3]; myArray[0] + myArray[1] + myArray[2];",
+                "6",
+            ),
+            ("[1, 2, 3][3]", "null"),
+            ("[1, 2, 3][-1]", "null"),
+            (
+                "[1, 2, 3][true]",
+                "index operator not supported: [1, 2, 3][true]",
+            ),
+            ("5[0]", "index operator not supported: 5[0]"),
+            ("[1, 2 + true]", "type mismatch: 2 + true"),
+        ];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_hash_literals() {
+        let test_case = [
+            ("{}", "{}"),
+            (
+                r#"let two = "two"; {"one": 10 - 9, two: 1 + 1, "thr" + "ee": 6 / 2, 4: 4, true: 5, false: 6}"#,
+                "{4: 4, false: 6, true: 5, one: 1, three: 3, two: 2}",
+            ),
+            (r#"{"a": 1, "a": 2}"#, "{a: 2}"),
+        ];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_hash_index_expressions() {
+        let test_case = [
+            (r#"{"foo": 5}["foo"]"#, "5"),
+            (r#"{"foo": 5}["bar"]"#, "null"),
+            (r#"let key = "foo"; {"foo": 5}[key]"#, "5"),
+            (r#"{}["foo"]"#, "null"),
+            ("{5: 5}[5]", "5"),
+            ("{true: 5}[true]", "5"),
+            ("{false: 5}[false]", "5"),
+            ("{1: 5}[true]", "null"),
+        ];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_unhashable_keys() {
+        let test_case = [
+            (
+                r#"{"name": "Monkey"}[fn(x) { x }];"#,
+                "unusable as hash key: FUNCTION",
+            ),
+            ("{[1]: 2}", "unusable as hash key: ARRAY"),
+            ("{{}: 2}", "unusable as hash key: HASH"),
+        ];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_builtin_functions() {
+        let test_case = [
+            (r#"len("")"#, "0"),
+            (r#"len("four")"#, "4"),
+            (r#"len("hello world")"#, "11"),
+            ("len([1, 2, 3])", "3"),
+            (r#"len({"a": 1})"#, "1"),
+            ("len(1)", "argument to `len` not supported, got INTEGER"),
+            (
+                r#"len("one", "two")"#,
+                "wrong number of arguments: expected 1, got 2",
+            ),
+            ("first([1, 2, 3])", "1"),
+            ("first([])", "null"),
+            ("first(1)", "argument to `first` not supported, got INTEGER"),
+            ("last([1, 2, 3])", "3"),
+            ("last([])", "null"),
+            ("rest([1, 2, 3])", "[2, 3]"),
+            ("rest([1])", "[]"),
+            ("rest([])", "null"),
+            ("push([], 1)", "[1]"),
+            ("let a = [1]; push(a, 2); a;", "[1]"),
+            (
+                "push(1, 1)",
+                "argument to `push` not supported, got INTEGER",
+            ),
+            ("puts()", "null"),
+            ("len", "builtin function len"),
+            ("let len = fn(x) { 42 }; len([1]);", "42"),
+        ];
+        apply_test_on_both_backends(&test_case);
+    }
+
+    #[test]
+    fn test_builtin_names() {
+        assert_eq!(
+            super::builtin::names(),
+            vec!["len", "first", "last", "rest", "push", "puts"]
+        );
+    }
+
+    #[test]
+    fn test_error_spans() {
+        let input = "let x = 1;\nlet y = x + true;";
+        let node = parse(input).expect("parse");
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let err = eval(node, &env).expect_err("type mismatch");
+        assert_eq!(err.span(), Some(Span::new(19, 27, 2, 9)));
+
+        let node = parse("[1, 2][0](foo)").expect("parse");
+        let err = eval(node, &env).expect_err("unknown identifier");
+        assert_eq!(err.span(), Some(Span::new(10, 13, 1, 11)));
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let eval_err = |input: &str| eval(parse(input).expect("parse"), &env).expect_err(input);
+
+        let err = eval_err("5 + true");
+        match err.kind() {
+            EvaluatorErrorKind::TypeMismatch { op, left, right } => {
+                assert_eq!(op, &Token::Plus);
+                assert_eq!(left.type_name, "INTEGER");
+                assert_eq!(right.type_name, "BOOLEAN");
+            }
+            kind => panic!("expected a type mismatch, got {:?}", kind),
+        }
+        assert_eq!(err.code(), "E0203");
+
+        let err = eval_err("missing");
+        assert!(matches!(
+            err.kind(),
+            EvaluatorErrorKind::UnknownIdentifier(name) if name == "missing"
+        ));
+        assert_eq!(err.code(), "E0200");
+        for input in ["undeclared = 1", "undeclared += 1"] {
+            assert_eq!(eval_err(input).code(), "E0201", "{}", input);
+        }
+
+        assert!(matches!(
+            eval_err("len(1, 2)").kind(),
+            EvaluatorErrorKind::WrongArgumentCount {
+                expected: 1,
+                got: 2
+            }
+        ));
+        assert!(matches!(
+            eval_err("1 % 0").kind(),
+            EvaluatorErrorKind::DivisionByZero { .. }
+        ));
+
+        // Operands are rendered into the error, cut short when large.
+        eval(parse("let big = 0..100000;").expect("parse"), &env).expect("let");
+        eval(parse("let big = [big, big];").expect("parse"), &env).expect("let");
+        for input in ["big + 1", "big(1)", "big[5] = 1", "-big"] {
+            let err = eval_err(input);
+            let operand = match err.kind() {
+                EvaluatorErrorKind::TypeMismatch { left, .. } => left,
+                EvaluatorErrorKind::NotAFunction(callee) => callee,
+                EvaluatorErrorKind::IndexOutOfRange { collection, .. } => collection,
+                EvaluatorErrorKind::UnknownPrefixOperator { operand, .. } => operand,
+                kind => panic!("unexpected error for {}: {:?}", input, kind),
+            };
+            assert_eq!(operand.type_name, "ARRAY", "{}", input);
+            assert_eq!(operand.text, "[0..100000, 0..100000]", "{}", input);
+        }
+        let input = "let long = []; for (i in 0..1000) { long = push(long, i) };";
+        eval(parse(input).expect("parse"), &env).expect("let");
+        match eval_err("long + 1").kind() {
+            EvaluatorErrorKind::TypeMismatch { left, right, .. } => {
+                assert_eq!(left.text.len(), MAX_OPERAND_LEN + "...".len());
+                assert!(left.text.starts_with("[0, 1, 2, "), "{}", left.text);
+                assert!(left.text.ends_with("..."), "{}", left.text);
+                assert_eq!(right.text, "1");
+            }
+            kind => panic!("expected a type mismatch, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_call_stack_trace() {
+        let input = "let inner = fn(x) { 10 / x };
+let outer = fn(x) { inner(x) };
+outer(0)";
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let err = eval(parse(input).expect("parse"), &env).expect_err("division by zero");
+
+        let frames: Vec<(&str, usize, usize)> = err
+            .trace()
+            .iter()
+            .map(|frame| {
+                let span = frame.call_site;
+                (frame.function.as_str(), span.line, span.column)
+            })
+            .collect();
+        assert_eq!(frames, vec![("inner", 2, 21), ("outer", 3, 1)]);
+        assert_eq!(err.span().map(|span| span.line), Some(1));
+
+        let err = eval(parse("fn() { -true }()").expect("parse"), &env).expect_err("prefix");
+        assert_eq!(err.trace().len(), 1);
+        assert_eq!(err.trace()[0].function, "<anonymous>");
+
+        // Errors raised before a body runs belong to the caller.
+        let err = eval(parse("inner(1, 2)").expect("parse"), &env).expect_err("arity");
+        assert!(err.trace().is_empty());
+        let err = eval(parse("len(1)").expect("parse"), &env).expect_err("builtin");
+        assert!(err.trace().is_empty());
+    }
+
+    #[test]
+    fn test_recursion_limit() {
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let config = EvalConfig::default().with_max_depth(50);
+        let run = |input: &str| eval_with(parse(input).expect("parse"), &env, &config);
+
+        run("let down = fn(n) { if (n == 0) { 0 } else { down(n - 1) } };").expect("let");
+        assert_eq!(run("down(10)").expect("shallow").to_string(), "0");
+
+        let err = run("down(100)").expect_err("too deep");
+        assert!(matches!(err.kind(), EvaluatorErrorKind::RecursionLimit(50)));
+        assert_eq!(err.code(), "E0221");
+        assert_eq!(
+            err.to_string(),
+            "maximum recursion depth exceeded: the limit is 50"
+        );
+        assert!(err.trace().iter().all(|frame| frame.function == "down"));
+
+        // The depth is released as the error unwinds.
+        assert_eq!(run("down(10)").expect("shallow").to_string(), "0");
+        assert_eq!(
+            eval(parse("down(30)").expect("parse"), &env)
+                .expect("default limit")
+                .to_string(),
+            "0"
+        );
+    }
+
+    #[test]
+    fn test_stack_limit() {
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let config = EvalConfig::default()
+            .with_max_depth(usize::MAX)
+            .with_max_stack(64 * 1024);
+        let run = |input: &str| eval_with(parse(input).expect("parse"), &env, &config);
+
+        run("let down = fn(n) { if (n == 0) { 0 } else { down(n - 1) } };").expect("let");
+        let err = run("down(100000)").expect_err("out of stack");
+        match err.kind() {
+            EvaluatorErrorKind::RecursionLimit(depth) => assert!(*depth < 100_000, "{}", depth),
+            kind => panic!("expected a recursion limit, got {:?}", kind),
+        }
+        assert_eq!(run("down(2)").expect("shallow").to_string(), "0");
+    }
+
+    #[test]
+    fn test_step_budget() {
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let config = EvalConfig::default().with_max_steps(100);
+        let run = |input: &str| eval_with(parse(input).expect("parse"), &env, &config);
+
+        for input in ["while (true) { }", "for (i in 0..1000000000) { }"] {
+            let err = run(input).expect_err(input);
+            assert!(matches!(
+                err.kind(),
+                EvaluatorErrorKind::LimitExceeded(Limit::Steps(100))
+            ));
+            assert_eq!(err.code(), "E0222");
+            assert_eq!(err.to_string(), "step budget of 100 exhausted");
+        }
+
+        // Every run gets the whole budget.
+        for _ in 0..3 {
+            assert_eq!(
+                run("let x = 0; while (x < 10) { x += 1 }; x")
+                    .expect("run")
+                    .to_string(),
+                "10"
+            );
+        }
+    }
+
+    #[test]
+    fn test_expensive_operations_take_steps() {
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let unlimited = EvalConfig::default();
+        let input = "let a = []; for (i in 0..1000) { a = push(a, i) }; let s = \"x\";";
+        eval_with(parse(input).expect("parse"), &env, &unlimited).expect("setup");
+
+        let config = EvalConfig::default().with_max_steps(10);
+        let run = |input: &str| eval_with(parse(input).expect("parse"), &env, &config);
+        for input in ["3 ** 100000", "push(a, 0)", "rest(a)", "a[0] = 1"] {
+            let err = run(input).expect_err(input);
+            assert!(
+                matches!(
+                    err.kind(),
+                    EvaluatorErrorKind::LimitExceeded(Limit::Steps(10))
+                ),
+                "{}: {:?}",
+                input,
+                err.kind()
+            );
+        }
+        // Small values still cost a single step.
+        assert_eq!(run("3 ** 10").expect("small").to_string(), "59049");
+        assert_eq!(run("s + s").expect("small").to_string(), "xx");
+    }
+
+    #[test]
+    fn test_deadline_stops_expensive_operations() {
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let unlimited = EvalConfig::default();
+        let input = "let s = \"x\"; for (i in 0..22) { s = s + s }; let n = 0;";
+        eval_with(parse(input).expect("parse"), &env, &unlimited).expect("setup");
+
+        // Each concatenation copies 8 MiB, far more than the steps between
+        // two polls of the clock, so the loop stops right after the first
+        // one that overruns the deadline.
+        let config = EvalConfig::default().with_timeout(Duration::from_millis(1));
+        let input = "while (true) { n += 1; s + s; }";
+        let err = eval_with(parse(input).expect("parse"), &env, &config).expect_err("deadline");
+        assert!(matches!(
+            err.kind(),
+            EvaluatorErrorKind::LimitExceeded(Limit::Deadline)
+        ));
+        let n = env.borrow().get("n").expect("count").to_string();
+        assert!(n.parse::<i64>().expect("integer") < 20, "{}", n);
+    }
+
+    #[test]
+    fn test_deadline_and_cancellation() {
+        let env: Env = Rc::new(RefCell::new(Default::default()));
+        let forever = || parse("while (true) { }").expect("parse");
+
+        let config = EvalConfig::default().with_timeout(Duration::from_millis(20));
+        let err = eval_with(forever(), &env, &config).expect_err("deadline");
+        assert!(matches!(
+            err.kind(),
+            EvaluatorErrorKind::LimitExceeded(Limit::Deadline)
+        ));
+        assert_eq!(err.to_string(), "deadline exceeded");
+
+        // The timeout starts afresh with every run.
+        thread::sleep(Duration::from_millis(30));
+        let result = eval_with(parse("1 + 1").expect("parse"), &env, &config).expect("in time");
+        assert_eq!(result.to_string(), "2");
+
+        let cancel = CancelHandle::new();
+        let config = EvalConfig::default().with_cancel(cancel.clone());
+        let canceller = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(20));
+            cancel.cancel();
+        });
+        let err = eval_with(forever(), &env, &config).expect_err("cancelled");
+        canceller.join().expect("join");
+        assert!(matches!(
+            err.kind(),
+            EvaluatorErrorKind::LimitExceeded(Limit::Cancelled)
+        ));
+        assert_eq!(err.to_string(), "evaluation cancelled");
+
+        // A cancelled handle stops later runs straight away.
+        let err = eval_with(parse("1").expect("parse"), &env, &config).expect_err("cancelled");
+        assert!(matches!(
+            err.kind(),
+            EvaluatorErrorKind::LimitExceeded(Limit::Cancelled)
+        ));
+    }
+
+    #[test]
+    fn test_let_statement_error_does_not_bind() {
+        let test_case = [
+            ("let y = 5 + true;", "type mismatch: 5 + true"),
+            ("y", "identifier not found: y"),
         ];
         apply_test(&test_case);
     }
@@ -0,0 +1,114 @@
+use std::fmt;
+
+use crate::span::Span;
+use crate::token::token::{SpannedToken, Trivia};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LexerError {
+    kind: LexerErrorKind,
+    span: Span,
+    recovered: Box<SpannedToken>,
+}
+
+/// What went wrong while reading tokens. Every kind has a stable code in the
+/// `E00xx` range, see [`LexerErrorKind::code`]. Literals are kept as written.
+#[derive(Debug, Clone, PartialEq)]
+pub enum LexerErrorKind {
+    UnterminatedString,
+    UnterminatedBlockComment,
+    InvalidEscape(char),
+    /// A `\u` that is not followed by `{`.
+    MissingUnicodeBrace,
+    /// A `\u{...}` without its closing brace or with no or too many digits.
+    InvalidUnicodeEscape(String),
+    /// A well-formed `\u{...}` naming a surrogate or a value past `10FFFF`.
+    InvalidCodePoint(String),
+    InvalidHexLiteral(String),
+    /// A hexadecimal literal that does not fit in an `i64`. Decimal literals
+    /// that large become big integers instead.
+    IntegerTooLarge(String),
+    FloatOutOfRange(String),
+}
+
+impl LexerErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            LexerErrorKind::UnterminatedString => "E0001",
+            LexerErrorKind::UnterminatedBlockComment => "E0002",
+            LexerErrorKind::InvalidEscape(_) => "E0003",
+            LexerErrorKind::MissingUnicodeBrace => "E0004",
+            LexerErrorKind::InvalidUnicodeEscape(_) => "E0005",
+            LexerErrorKind::InvalidCodePoint(_) => "E0006",
+            LexerErrorKind::InvalidHexLiteral(_) => "E0007",
+            LexerErrorKind::IntegerTooLarge(_) => "E0008",
+            LexerErrorKind::FloatOutOfRange(_) => "E0009",
+        }
+    }
+}
+
+impl fmt::Display for LexerErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LexerErrorKind::UnterminatedString => write!(f, "unterminated string"),
+            LexerErrorKind::UnterminatedBlockComment => write!(f, "unterminated block comment"),
+            LexerErrorKind::InvalidEscape(ch) => write!(f, "invalid escape sequence: \\{}", ch),
+            LexerErrorKind::MissingUnicodeBrace => {
+                write!(f, "invalid unicode escape: expected {{")
+            }
+            LexerErrorKind::InvalidUnicodeEscape(escape) => {
+                write!(f, "invalid unicode escape: {}", escape)
+            }
+            LexerErrorKind::InvalidCodePoint(escape) => {
+                write!(f, "invalid unicode code point: {}", escape)
+            }
+            LexerErrorKind::InvalidHexLiteral(literal) => {
+                write!(f, "invalid hexadecimal literal: {}", literal)
+            }
+            LexerErrorKind::IntegerTooLarge(literal) => {
+                write!(f, "integer literal too large: {}", literal)
+            }
+            LexerErrorKind::FloatOutOfRange(literal) => {
+                write!(f, "float literal out of range: {}", literal)
+            }
+        }
+    }
+}
+
+impl fmt::Display for LexerError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl LexerError {
+    pub fn new(kind: LexerErrorKind, span: Span, recovered: SpannedToken) -> Self {
+        LexerError {
+            kind,
+            span,
+            recovered: Box::new(recovered),
+        }
+    }
+
+    pub fn kind(&self) -> &LexerErrorKind {
+        &self.kind
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+
+    /// The best-effort token the lexer produced in place of the bad one, so
+    /// that callers can keep going after reporting the error.
+    pub fn recovered(&self) -> &SpannedToken {
+        &self.recovered
+    }
+
+    pub(crate) fn with_leading_trivia(mut self, trivia: Vec<Trivia>) -> Self {
+        self.recovered.leading_trivia = trivia;
+        self
+    }
+}
@@ -1,12 +1,25 @@
+pub mod error;
+
 pub mod lexer {
 
-    use crate::token::token::Token;
+    use super::error::{LexerError, LexerErrorKind};
+    use crate::span::Span;
+    use crate::token::token::{lookup_ident, SpannedToken, Token, Trivia, TriviaKind};
 
+    /// Converts source text into tokens.
+    ///
+    /// `position` and `read_position` are byte offsets into `input`: `ch` is the
+    /// character starting at `position`, and `read_position` is where the next
+    /// one starts.
     pub struct Lexer {
         pub input: String,
         pub position: usize,
         pub read_position: usize,
         pub ch: char,
+        pub line: usize,
+        pub column: usize,
+        /// Whether whitespace and comments are kept as `Trivia` on tokens.
+        pub lossless: bool,
     }
 
     impl Lexer {
@@ -16,26 +29,60 @@ pub mod lexer {
                 position: 0,
                 read_position: 0,
                 ch: 0 as char,
+                line: 1,
+                column: 0,
+                lossless: false,
             };
             l.read_char();
 
             l
         }
 
+        /// Creates a lexer that attaches whitespace and comments to the token
+        /// that follows them, so the input can be reproduced exactly.
+        pub fn new_lossless(input: &str) -> Lexer {
+            Lexer {
+                lossless: true,
+                ..Lexer::new(input)
+            }
+        }
+
         pub fn read_char(&mut self) {
-            if self.read_position >= self.input.len() {
-                self.ch = 0 as char;
+            if self.ch == '\n' {
+                self.line += 1;
+                self.column = 1;
             } else {
-                self.ch = self.input.chars().nth(self.read_position).unwrap();
+                self.column += 1;
             }
             self.position = self.read_position;
-            self.read_position += 1;
+            match self.input[self.read_position..].chars().next() {
+                Some(ch) => {
+                    self.ch = ch;
+                    self.read_position += ch.len_utf8();
+                }
+                None => self.ch = 0 as char,
+            }
         }
 
-        pub fn next_token(&mut self) -> Result<Token, String> {
-            let token: Token;
+        pub fn next_spanned_token(&mut self) -> Result<SpannedToken, LexerError> {
+            let leading_trivia = self.skip_trivia()?;
+            let (start, line, column) = (self.position, self.line, self.column);
+            match self.read_token() {
+                Ok(token) => Ok(SpannedToken {
+                    token,
+                    span: Span::new(start, self.position, line, column),
+                    leading_trivia,
+                }),
+                Err(err) => Err(err.with_leading_trivia(leading_trivia)),
+            }
+        }
+
+        pub fn next_token(&mut self) -> Result<Token, LexerError> {
+            self.next_spanned_token().map(|spanned| spanned.token)
+        }
 
-            self.skip_whitespace();
+        fn read_token(&mut self) -> Result<Token, LexerError> {
+            let token: Token;
 
             match self.ch {
                 '=' => {
@@ -54,39 +101,65 @@ pub mod lexer {
                         token = Token::Bang;
                     }
                 }
+                '&' if self.peek_char() == '&' => {
+                    self.read_char();
+                    token = Token::And;
+                }
+                '|' if self.peek_char() == '|' => {
+                    self.read_char();
+                    token = Token::Or;
+                }
+                '.' if self.peek_char() == '.' => {
+                    self.read_char();
+                    token = Token::DotDot;
+                }
                 ';' => token = Token::Semicolon,
                 '(' => token = Token::LParen,
                 ')' => token = Token::RParen,
                 ',' => token = Token::Comma,
+                ':' => token = Token::Colon,
+                '+' | '-' | '*' | '/' | '%' if self.peek_char() == '=' => {
+                    token = match self.ch {
+                        '+' => Token::PlusAssign,
+                        '-' => Token::MinusAssign,
+                        '*' => Token::AsteriskAssign,
+                        '/' => Token::SlashAssign,
+                        _ => Token::PercentAssign,
+                    };
+                    self.read_char();
+                }
                 '+' => token = Token::Plus,
                 '-' => token = Token::Minus,
                 '/' => token = Token::Slash,
+                '%' => token = Token::Percent,
+                '*' if self.peek_char() == '*' => {
+                    self.read_char();
+                    token = Token::Power;
+                }
                 '*' => token = Token::Asterisk,
+                '<' if self.peek_char() == '=' => {
+                    self.read_char();
+                    token = Token::LtEq;
+                }
                 '<' => token = Token::Lt,
+                '>' if self.peek_char() == '=' => {
+                    self.read_char();
+                    token = Token::GtEq;
+                }
                 '>' => token = Token::Gt,
                 '{' => token = Token::LBrace,
                 '}' => token = Token::RBrace,
                 '[' => token = Token::LBracket,
                 ']' => token = Token::RBracket,
                 '\0' => token = Token::Eof,
-                '"' => {
-                    self.read_char();
-                    let start_position = self.position;
-                    let mut end_position = start_position;
-                    while self.ch != '"' {
-                        self.read_char();
-                        end_position += 1;
-                    }
-                    token = Token::String(self.input[start_position..end_position].to_string());
-                }
+                '"' => return self.read_string(),
                 _ => {
                     if self.ch.is_alphabetic() {
                         return Ok(self.read_keyword_or_ident());
-                    } else if self.ch.is_numeric() {
-                        let n = self.read_number();
-                        return Ok(n);
+                    } else if self.ch.is_ascii_digit() {
+                        return self.read_number();
                     } else {
-                        return Ok(Token::Illegal);
+                        token = Token::Illegal;
                     }
                 }
             }
@@ -94,30 +167,155 @@ pub mod lexer {
             Ok(token)
         }
 
-        fn peek_char(&self) -> char {
-            if self.read_position >= self.input.len() {
-                0 as char
-            } else {
-                return self.input.chars().nth(self.read_position).unwrap();
+        fn at_eof(&self) -> bool {
+            self.position >= self.input.len()
+        }
+
+        /// Reads a string literal, decoding its escape sequences. On error the
+        /// whole literal is still consumed and recovered as a string token.
+        fn read_string(&mut self) -> Result<Token, LexerError> {
+            let (start, line, column) = (self.position, self.line, self.column);
+            let mut value = String::new();
+            let mut error = None;
+            self.read_char();
+
+            while self.ch != '"' {
+                if self.at_eof() {
+                    let span = Span::new(start, self.position, line, column);
+                    error = Some((LexerErrorKind::UnterminatedString, span));
+                    break;
+                }
+                if self.ch != '\\' {
+                    value.push(self.ch);
+                    self.read_char();
+                    continue;
+                }
+
+                let (escape_start, escape_line, escape_column) =
+                    (self.position, self.line, self.column);
+                self.read_char();
+                match self.read_escape() {
+                    Ok(ch) => value.push(ch),
+                    Err(kind) => {
+                        let span =
+                            Span::new(escape_start, self.position, escape_line, escape_column);
+                        error.get_or_insert((kind, span));
+                    }
+                }
+            }
+            self.read_char();
+
+            let token = Token::String(value);
+            match error {
+                Some((kind, span)) => {
+                    let recovered = SpannedToken {
+                        token,
+                        span: Span::new(start, self.position, line, column),
+                        leading_trivia: Vec::new(),
+                    };
+                    Err(LexerError::new(kind, span, recovered))
+                }
+                None => Ok(token),
+            }
+        }
+
+        /// Decodes the escape sequence following a backslash.
+        fn read_escape(&mut self) -> Result<char, LexerErrorKind> {
+            let escaped = match self.ch {
+                'n' => '\n',
+                't' => '\t',
+                'r' => '\r',
+                '0' => '\0',
+                '"' => '"',
+                '\\' => '\\',
+                'u' => return self.read_unicode_escape(),
+                _ if self.at_eof() => return Err(LexerErrorKind::UnterminatedString),
+                other => {
+                    self.read_char();
+                    return Err(LexerErrorKind::InvalidEscape(other));
+                }
+            };
+            self.read_char();
+            Ok(escaped)
+        }
+
+        /// Decodes `u{XXXX}` with one to six hex digits.
+        fn read_unicode_escape(&mut self) -> Result<char, LexerErrorKind> {
+            self.read_char();
+            if self.ch != '{' {
+                return Err(LexerErrorKind::MissingUnicodeBrace);
+            }
+            self.read_char();
+            let digits_start = self.position;
+            while self.ch.is_ascii_hexdigit() {
+                self.read_char();
+            }
+            let digits = self.input[digits_start..self.position].to_string();
+            if self.ch != '}' {
+                return Err(LexerErrorKind::InvalidUnicodeEscape(format!(
+                    "\\u{{{}",
+                    digits
+                )));
+            }
+            self.read_char();
+            if digits.is_empty() || digits.len() > 6 {
+                return Err(LexerErrorKind::InvalidUnicodeEscape(format!(
+                    "\\u{{{}}}",
+                    digits
+                )));
             }
+            u32::from_str_radix(&digits, 16)
+                .ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| LexerErrorKind::InvalidCodePoint(format!("\\u{{{}}}", digits)))
+        }
+
+        fn peek_char(&self) -> char {
+            self.input[self.read_position..]
+                .chars()
+                .next()
+                .unwrap_or(0 as char)
         }
 
         fn read_keyword_or_ident(&mut self) -> Token {
             let start_position = self.position;
-            let mut end_position = start_position;
             while self.ch.is_alphanumeric() {
                 self.read_char();
-                end_position += 1;
             }
-            match &self.input[start_position..end_position] {
-                "fn" => Token::Fn,
-                "let" => Token::Let,
-                "if" => Token::If,
-                "else" => Token::Else,
-                "return" => Token::Return,
-                "true" => Token::Boolean(true),
-                "false" => Token::Boolean(false),
-                ident => Token::Ident(ident.to_string()),
+            lookup_ident(&self.input[start_position..self.position])
+        }
+
+        /// Skips whitespace, `//` line comments and nestable `/* */` block
+        /// comments. In lossless mode the skipped text is returned as trivia.
+        fn skip_trivia(&mut self) -> Result<Vec<Trivia>, LexerError> {
+            let mut trivia = Vec::new();
+            loop {
+                let (start, line, column) = (self.position, self.line, self.column);
+                let kind = match (self.ch, self.peek_char()) {
+                    (' ' | '\t' | '\n' | '\r', _) => {
+                        self.skip_whitespace();
+                        TriviaKind::Whitespace
+                    }
+                    ('/', '/') => {
+                        while self.ch != '\n' && !self.at_eof() {
+                            self.read_char();
+                        }
+                        TriviaKind::LineComment
+                    }
+                    ('/', '*') => {
+                        if !self.skip_block_comment() {
+                            let span = Span::new(start, self.position, line, column);
+                            trivia.push(self.trivia(TriviaKind::BlockComment, span));
+                            return Err(self.unterminated_block_comment(span, trivia));
+                        }
+                        TriviaKind::BlockComment
+                    }
+                    _ => return Ok(trivia),
+                };
+                let span = Span::new(start, self.position, line, column);
+                if self.lossless {
+                    trivia.push(self.trivia(kind, span));
+                }
             }
         }
 
@@ -127,26 +325,144 @@ pub mod lexer {
             }
         }
 
-        fn read_number(&mut self) -> Token {
-            let start_position = self.position;
-            let mut end_position = start_position;
+        /// Skips a block comment, returning false if the input ends first.
+        fn skip_block_comment(&mut self) -> bool {
+            let mut depth = 0;
+            loop {
+                match (self.ch, self.peek_char()) {
+                    ('/', '*') => {
+                        depth += 1;
+                        self.read_char();
+                    }
+                    ('*', '/') => {
+                        depth -= 1;
+                        self.read_char();
+                        if depth == 0 {
+                            self.read_char();
+                            return true;
+                        }
+                    }
+                    _ if self.at_eof() => return false,
+                    _ => (),
+                }
+                self.read_char();
+            }
+        }
 
-            while self.ch.is_numeric() {
+        fn trivia(&self, kind: TriviaKind, span: Span) -> Trivia {
+            Trivia {
+                kind,
+                text: self.input[span.start..span.end].to_string(),
+                span,
+            }
+        }
+
+        fn unterminated_block_comment(&self, span: Span, trivia: Vec<Trivia>) -> LexerError {
+            let eof = SpannedToken {
+                token: Token::Eof,
+                span: Span::new(self.position, self.position, self.line, self.column),
+                leading_trivia: if self.lossless { trivia } else { Vec::new() },
+            };
+            LexerError::new(LexerErrorKind::UnterminatedBlockComment, span, eof)
+        }
+
+        /// Reads a decimal integer (`42`), hexadecimal integer (`0x1F`) or
+        /// float (`3.14`, `1e-9`). A float needs a digit after its `.` so that
+        /// `1.` stays an integer followed by a dot.
+        fn read_number(&mut self) -> Result<Token, LexerError> {
+            let (start, line, column) = (self.position, self.line, self.column);
+
+            if self.ch == '0' && matches!(self.peek_char(), 'x' | 'X') {
+                self.read_char();
                 self.read_char();
-                end_position += 1;
+                self.read_digits(|c| c.is_ascii_hexdigit());
+                let literal = &self.input[start..self.position];
+                let span = Span::new(start, self.position, line, column);
+                return match i64::from_str_radix(&literal[2..], 16) {
+                    Ok(num) => Ok(Token::Int(num)),
+                    Err(_) if literal.len() == 2 => Err(Self::number_error(
+                        LexerErrorKind::InvalidHexLiteral(literal.to_string()),
+                        span,
+                        Token::Int(0),
+                    )),
+                    Err(_) => Err(Self::number_error(
+                        LexerErrorKind::IntegerTooLarge(literal.to_string()),
+                        span,
+                        Token::Int(0),
+                    )),
+                };
             }
-            match self.input[start_position..end_position].parse() {
-                Ok(num) => Token::Int(num),
-                Err(_msg) => Token::Illegal,
+
+            let mut is_float = false;
+            self.read_digits(|c| c.is_ascii_digit());
+            if self.ch == '.' && self.peek_char().is_ascii_digit() {
+                is_float = true;
+                self.read_char();
+                self.read_digits(|c| c.is_ascii_digit());
+            }
+            if matches!(self.ch, 'e' | 'E') && self.exponent_follows() {
+                is_float = true;
+                self.read_char();
+                if matches!(self.ch, '+' | '-') {
+                    self.read_char();
+                }
+                self.read_digits(|c| c.is_ascii_digit());
+            }
+
+            let literal = &self.input[start..self.position];
+            let span = Span::new(start, self.position, line, column);
+            if is_float {
+                match literal.parse::<f64>() {
+                    Ok(num) if num.is_finite() => Ok(Token::Float(num)),
+                    _ => Err(Self::number_error(
+                        LexerErrorKind::FloatOutOfRange(literal.to_string()),
+                        span,
+                        Token::Float(0.0),
+                    )),
+                }
+            } else {
+                // A run of digits only fails to parse as an i64 by overflowing.
+                Ok(match literal.parse() {
+                    Ok(num) => Token::Int(num),
+                    Err(_) => literal.parse().map_or(Token::Illegal, Token::BigInt),
+                })
             }
         }
+
+        fn read_digits(&mut self, is_digit: fn(char) -> bool) {
+            while is_digit(self.ch) {
+                self.read_char();
+            }
+        }
+
+        /// Whether the `e` at the cursor starts an exponent, i.e. is followed
+        /// by digits with an optional sign.
+        fn exponent_follows(&self) -> bool {
+            match &self.input.as_bytes()[self.read_position..] {
+                [b'+' | b'-', digit, ..] | [digit, ..] => digit.is_ascii_digit(),
+                [] => false,
+            }
+        }
+
+        fn number_error(kind: LexerErrorKind, span: Span, recovered: Token) -> LexerError {
+            let recovered = SpannedToken {
+                token: recovered,
+                span,
+                leading_trivia: Vec::new(),
+            };
+            LexerError::new(kind, span, recovered)
+        }
     }
 }
 
 #[cfg(test)]
 mod lexer_tests {
-    use crate::token::token::Token;
+    use num_bigint::BigInt;
+
+    use crate::span::Span;
+    use crate::token::token::{Token, TriviaKind};
 
+    use super::error::LexerErrorKind;
     use super::{lexer::Lexer, *};
 
     #[test]
@@ -245,6 +561,383 @@ mod lexer_tests {
         assert_eq!(data, tests);
     }
 
+    #[test]
+    fn test_token_spans() {
+        let input = "let x = 10;\n  x";
+
+        let tests = vec![
+            (Token::Let, Span::new(0, 3, 1, 1)),
+            (Token::Ident("x".to_string()), Span::new(4, 5, 1, 5)),
+            (Token::Assign, Span::new(6, 7, 1, 7)),
+            (Token::Int(10), Span::new(8, 10, 1, 9)),
+            (Token::Semicolon, Span::new(10, 11, 1, 11)),
+            (Token::Ident("x".to_string()), Span::new(14, 15, 2, 3)),
+            (Token::Eof, Span::new(15, 15, 2, 4)),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (expected_token, expected_span) in tests {
+            let spanned = lexer.next_spanned_token().expect("token");
+            assert_eq!(spanned.token, expected_token);
+            assert_eq!(spanned.span, expected_span);
+        }
+    }
+
+    #[test]
+    fn test_multibyte_input() {
+        let input = "let naïve = \"héllo 🐒\"; naïve";
+
+        let tests = vec![
+            (Token::Let, Span::new(0, 3, 1, 1)),
+            (Token::Ident("naïve".to_string()), Span::new(4, 10, 1, 5)),
+            (Token::Assign, Span::new(11, 12, 1, 11)),
+            (
+                Token::String("héllo 🐒".to_string()),
+                Span::new(13, 26, 1, 13),
+            ),
+            (Token::Semicolon, Span::new(26, 27, 1, 22)),
+            (Token::Ident("naïve".to_string()), Span::new(28, 34, 1, 24)),
+            (Token::Eof, Span::new(34, 34, 1, 29)),
+        ];
+
+        let mut lexer = Lexer::new(input);
+        for (expected_token, expected_span) in tests {
+            let spanned = lexer.next_spanned_token().expect("token");
+            assert_eq!(spanned.token, expected_token);
+            assert_eq!(spanned.span, expected_span);
+        }
+    }
+
+    #[test]
+    fn test_illegal_character_is_consumed() {
+        let mut lexer = Lexer::new("@ 1");
+        assert_eq!(lexer.next_token(), Ok(Token::Illegal));
+        assert_eq!(lexer.next_token(), Ok(Token::Int(1)));
+        assert_eq!(lexer.next_token(), Ok(Token::Eof));
+    }
+
+    #[test]
+    fn test_string_escapes() {
+        let input = r#""a\nb\tc\r\"q\" \\ \u{1F412} \u{e9}""#;
+        let mut lexer = Lexer::new(input);
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::String("a\nb\tc\r\"q\" \\ 🐒 é".to_string()))
+        );
+        assert_eq!(lexer.next_token(), Ok(Token::Eof));
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        let mut lexer = Lexer::new("let s = \"abc");
+        for _ in 0..3 {
+            lexer.next_token().expect("token");
+        }
+        let err = lexer.next_token().expect_err("unterminated");
+        assert_eq!(err.to_string(), "unterminated string");
+        assert_eq!(err.kind(), &LexerErrorKind::UnterminatedString);
+        assert_eq!(err.span(), Span::new(8, 12, 1, 9));
+        assert_eq!(err.recovered().token, Token::String("abc".to_string()));
+        assert_eq!(lexer.next_token(), Ok(Token::Eof));
+    }
+
+    #[test]
+    fn test_invalid_escapes() {
+        let tests = [
+            (
+                r#""a\qb" 1"#,
+                "invalid escape sequence: \\q",
+                Span::new(2, 4, 1, 3),
+            ),
+            (
+                r#""\u{110000}" 1"#,
+                "invalid unicode code point: \\u{110000}",
+                Span::new(1, 11, 1, 2),
+            ),
+            (
+                r#""\u{}" 1"#,
+                "invalid unicode escape: \\u{}",
+                Span::new(1, 5, 1, 2),
+            ),
+            (
+                r#""\u{12" 1"#,
+                "invalid unicode escape: \\u{12",
+                Span::new(1, 6, 1, 2),
+            ),
+            (
+                r#""\u12" 1"#,
+                "invalid unicode escape: expected {",
+                Span::new(1, 3, 1, 2),
+            ),
+        ];
+
+        for (input, expected_msg, expected_span) in tests {
+            let mut lexer = Lexer::new(input);
+            let err = lexer.next_token().expect_err(input);
+            assert_eq!(err.to_string(), expected_msg);
+            assert_eq!(err.span(), expected_span);
+            assert!(matches!(err.recovered().token, Token::String(_)));
+            assert_eq!(lexer.next_token(), Ok(Token::Int(1)), "{}", input);
+        }
+    }
+
+    fn tokens(input: &str) -> Vec<Token> {
+        let mut lexer = Lexer::new(input);
+        let mut data = Vec::new();
+        loop {
+            let token = lexer.next_token().expect("token");
+            data.push(token.clone());
+            if token == Token::Eof {
+                return data;
+            }
+        }
+    }
+
+    #[test]
+    fn test_comments_are_skipped() {
+        let input = "// leading\nlet x = 1; // trailing\n/* block /* nested */ still */ x / 2 /**/";
+
+        let tests = vec![
+            Token::Let,
+            Token::Ident("x".to_string()),
+            Token::Assign,
+            Token::Int(1),
+            Token::Semicolon,
+            Token::Ident("x".to_string()),
+            Token::Slash,
+            Token::Int(2),
+            Token::Eof,
+        ];
+        assert_eq!(tokens(input), tests);
+        assert_eq!(tokens("1 // no newline"), vec![Token::Int(1), Token::Eof]);
+    }
+
+    #[test]
+    fn test_lossless_trivia() {
+        let input = "let x = 1; // one\n/* a /* b */ c */\tx";
+        let mut lexer = Lexer::new_lossless(input);
+        let mut rebuilt = String::new();
+        let mut comments = Vec::new();
+        loop {
+            let spanned = lexer.next_spanned_token().expect("token");
+            for trivia in &spanned.leading_trivia {
+                rebuilt.push_str(&trivia.text);
+                if trivia.kind != TriviaKind::Whitespace {
+                    comments.push((trivia.kind, trivia.text.clone()));
+                }
+            }
+            rebuilt.push_str(&input[spanned.span.start..spanned.span.end]);
+            if spanned.token == Token::Eof {
+                break;
+            }
+        }
+
+        assert_eq!(rebuilt, input);
+        assert_eq!(
+            comments,
+            vec![
+                (TriviaKind::LineComment, "// one".to_string()),
+                (TriviaKind::BlockComment, "/* a /* b */ c */".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_trivia_is_dropped_by_default() {
+        let mut lexer = Lexer::new("  // comment\n x");
+        let spanned = lexer.next_spanned_token().expect("token");
+        assert_eq!(spanned.token, Token::Ident("x".to_string()));
+        assert!(spanned.leading_trivia.is_empty());
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        let input = "1 /* open /* nested */";
+        let mut lexer = Lexer::new_lossless(input);
+        assert_eq!(lexer.next_token(), Ok(Token::Int(1)));
+        let err = lexer.next_token().expect_err("unterminated");
+        assert_eq!(err.to_string(), "unterminated block comment");
+        assert_eq!(err.span(), Span::new(2, 22, 1, 3));
+        assert_eq!(err.recovered().token, Token::Eof);
+        assert_eq!(
+            err.recovered()
+                .leading_trivia
+                .last()
+                .map(|t| t.text.as_str()),
+            Some("/* open /* nested */")
+        );
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let tests = vec![
+            Token::Boolean(true),
+            Token::And,
+            Token::Ident("x".to_string()),
+            Token::Or,
+            Token::Bang,
+            Token::Ident("y".to_string()),
+            Token::Illegal,
+            Token::Illegal,
+            Token::Eof,
+        ];
+        assert_eq!(tokens("true && x || !y & |"), tests);
+    }
+
+    #[test]
+    fn test_comparison_and_arithmetic_operators() {
+        let tests = vec![
+            Token::Int(1),
+            Token::LtEq,
+            Token::Int(2),
+            Token::GtEq,
+            Token::Int(3),
+            Token::Percent,
+            Token::Int(4),
+            Token::Power,
+            Token::Int(5),
+            Token::Asterisk,
+            Token::Lt,
+            Token::Gt,
+            Token::Eof,
+        ];
+        assert_eq!(tokens("1 <= 2 >= 3 % 4 ** 5 * < >"), tests);
+    }
+
+    #[test]
+    fn test_loop_keywords() {
+        let tests = vec![
+            Token::While,
+            Token::LParen,
+            Token::Boolean(true),
+            Token::RParen,
+            Token::LBrace,
+            Token::Break,
+            Token::Semicolon,
+            Token::Continue,
+            Token::RBrace,
+            Token::Ident("whiles".to_string()),
+            Token::Eof,
+        ];
+
+        assert_eq!(tokens("while (true) { break; continue } whiles"), tests);
+    }
+
+    #[test]
+    fn test_for_in_range() {
+        let tests = vec![
+            Token::For,
+            Token::LParen,
+            Token::Ident("x".to_string()),
+            Token::In,
+            Token::Int(0),
+            Token::DotDot,
+            Token::Int(10),
+            Token::RParen,
+            Token::Eof,
+        ];
+
+        assert_eq!(tokens("for (x in 0..10)"), tests);
+    }
+
+    #[test]
+    fn test_assignment_operators() {
+        let tests = vec![
+            Token::PlusAssign,
+            Token::MinusAssign,
+            Token::AsteriskAssign,
+            Token::SlashAssign,
+            Token::PercentAssign,
+            Token::Assign,
+            Token::Eq,
+            Token::Power,
+            Token::Asterisk,
+            Token::Eof,
+        ];
+
+        assert_eq!(tokens("+= -= *= /= %= = == ** *"), tests);
+    }
+
+    #[test]
+    fn test_big_integer_literals() {
+        let mut lexer = Lexer::new("9223372036854775807 9223372036854775808;");
+        assert_eq!(lexer.next_token(), Ok(Token::Int(i64::MAX)));
+        assert_eq!(
+            lexer.next_token(),
+            Ok(Token::BigInt(BigInt::from(i64::MAX) + 1))
+        );
+        assert_eq!(lexer.next_token(), Ok(Token::Semicolon));
+    }
+
+    #[test]
+    fn test_number_literals() {
+        let tests = vec![
+            Token::Float(2.75),
+            Token::Float(1e-9),
+            Token::Float(2.5e3),
+            Token::Float(1e10),
+            Token::Int(0x1F),
+            Token::Int(255),
+            Token::Int(1),
+            Token::Illegal,
+            Token::Ident("e".to_string()),
+            Token::Int(7),
+            Token::Ident("e".to_string()),
+            Token::Plus,
+            Token::Eof,
+        ];
+
+        assert_eq!(tokens("2.75 1e-9 2.5E+3 1e10 0x1F 0XfF 1. e 7e + "), tests);
+    }
+
+    #[test]
+    fn test_invalid_number_literals() {
+        let mut lexer = Lexer::new("0x 1e999");
+        let err = lexer.next_token().expect_err("no hex digits");
+        assert_eq!(err.to_string(), "invalid hexadecimal literal: 0x");
+        let err = lexer.next_token().expect_err("out of range");
+        assert_eq!(err.to_string(), "float literal out of range: 1e999");
+        assert_eq!(err.recovered().token, Token::Float(0.0));
+    }
+
+    #[test]
+    fn test_error_codes() {
+        let tests = [
+            (r#""abc"#, LexerErrorKind::UnterminatedString),
+            ("/* abc", LexerErrorKind::UnterminatedBlockComment),
+            (r#""\q""#, LexerErrorKind::InvalidEscape('q')),
+            (r#""\u12""#, LexerErrorKind::MissingUnicodeBrace),
+            (
+                r#""\u{12""#,
+                LexerErrorKind::InvalidUnicodeEscape(r"\u{12".to_string()),
+            ),
+            (
+                r#""\u{D800}""#,
+                LexerErrorKind::InvalidCodePoint(r"\u{D800}".to_string()),
+            ),
+            ("0x", LexerErrorKind::InvalidHexLiteral("0x".to_string())),
+            (
+                "0x10000000000000000",
+                LexerErrorKind::IntegerTooLarge("0x10000000000000000".to_string()),
+            ),
+            (
+                "1e999",
+                LexerErrorKind::FloatOutOfRange("1e999".to_string()),
+            ),
+        ];
+
+        let mut codes = Vec::new();
+        for (input, expected) in tests {
+            let err = Lexer::new(input).next_token().expect_err(input);
+            assert_eq!(err.kind(), &expected, "{}", input);
+            assert_eq!(err.code(), expected.code());
+            codes.push(err.code());
+        }
+        codes.sort();
+        codes.dedup();
+        assert_eq!(codes.len(), 9, "every kind has its own code");
+    }
+
     #[test]
     fn test_boolean_expression() {
         let input = "true;";
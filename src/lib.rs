@@ -1,7 +1,16 @@
+#![allow(clippy::module_inception)]
+
 pub mod ast;
+pub mod code;
+pub mod compiler;
+pub mod diagnostics;
 pub mod eval;
 pub mod lexer;
 pub mod object;
 pub mod parser;
 pub mod repl;
+pub mod runner;
+pub mod span;
+pub mod stack;
 pub mod token;
+pub mod vm;
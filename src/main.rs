@@ -1,5 +1,158 @@
+use std::io::{IsTerminal, Read};
+use std::{env, fs, io, process};
+
 use interpreter_rust::repl::repl;
+use interpreter_rust::runner::{
+    self, Backend, Session, DEFAULT_STACK_SIZE, EXIT_NO_INPUT, EXIT_USAGE,
+};
+
+const USAGE: &str = "\
+usage: interpreter-rust [options] [script | -]
+
+Starts the REPL when no script is given.
+
+options:
+  -e <code>              evaluate <code> instead of reading a script
+  -                      read the program from stdin
+  --backend <eval|vm>    choose the tree-walking evaluator (default) or the bytecode VM,
+                         which does not support loops or assignment yet
+  --max-depth <n>        limit how deeply expressions and function calls may nest
+  --stack-size <MiB>     stack size of the interpreter thread (default 64)
+  -h, --help             print this message";
+
+struct Options {
+    input: Input,
+    backend: Backend,
+    max_depth: Option<usize>,
+    stack_size: usize,
+}
+
+enum Input {
+    Repl,
+    Code(String),
+    File(String),
+    Stdin,
+}
+
+fn parse_args(args: &[String]) -> Result<Options, String> {
+    let mut input = Input::Repl;
+    let mut backend = Backend::default();
+    let mut max_depth = None;
+    let mut stack_size = DEFAULT_STACK_SIZE;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let next = match arg.as_str() {
+            "--backend" => match args.next() {
+                Some(name) => {
+                    backend = name.parse()?;
+                    continue;
+                }
+                None => return Err("--backend requires an argument".to_string()),
+            },
+            "--max-depth" => {
+                max_depth = Some(number_argument(arg, args.next())?);
+                continue;
+            }
+            "--stack-size" => {
+                stack_size = number_argument(arg, args.next())?
+                    .checked_mul(1024 * 1024)
+                    .ok_or("--stack-size is too large")?;
+                continue;
+            }
+            "-e" => match args.next() {
+                Some(code) => Input::Code(code.clone()),
+                None => return Err("-e requires an argument".to_string()),
+            },
+            "-" => Input::Stdin,
+            "-h" | "--help" => {
+                println!("{}", USAGE);
+                process::exit(0);
+            }
+            flag if flag.starts_with('-') => return Err(format!("unknown option: {}", flag)),
+            path => Input::File(path.to_string()),
+        };
+        if !matches!(input, Input::Repl) {
+            return Err("only one program may be given".to_string());
+        }
+        input = next;
+    }
+    Ok(Options {
+        input,
+        backend,
+        max_depth,
+        stack_size,
+    })
+}
+
+fn number_argument(flag: &str, value: Option<&String>) -> Result<usize, String> {
+    let value = value.ok_or_else(|| format!("{} requires an argument", flag))?;
+    value
+        .parse()
+        .map_err(|_| format!("{} expects a number, got {}", flag, value))
+}
+
+fn read_source(input: &Input) -> io::Result<String> {
+    match input {
+        Input::Code(code) => Ok(code.clone()),
+        Input::File(path) => fs::read_to_string(path),
+        Input::Stdin => {
+            let mut source = String::new();
+            io::stdin().read_to_string(&mut source)?;
+            Ok(source)
+        }
+        Input::Repl => unreachable!("the REPL does not read a source up front"),
+    }
+}
 
 fn main() {
-    repl::start();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let options = match parse_args(&args) {
+        Ok(options) => options,
+        Err(msg) => {
+            eprintln!("error: {}\n\n{}", msg, USAGE);
+            process::exit(EXIT_USAGE);
+        }
+    };
+
+    let stack_size = options.stack_size;
+    match runner::with_stack_size(stack_size, move || run(options)) {
+        Ok(code) => process::exit(code),
+        Err(err) => {
+            eprintln!("error: could not start the interpreter thread: {}", err);
+            process::exit(1);
+        }
+    }
+}
+
+/// Runs the REPL or the given program and returns the exit code.
+fn run(options: Options) -> i32 {
+    let mut session =
+        Session::new(options.backend).with_max_stack(runner::max_stack_for(options.stack_size));
+    if let Some(max_depth) = options.max_depth {
+        session = session.with_max_depth(max_depth);
+    }
+
+    if let Input::Repl = options.input {
+        if let Err(err) = repl::start(session) {
+            eprintln!("error: {}", err);
+            return 1;
+        }
+        return 0;
+    }
+
+    let source = match read_source(&options.input) {
+        Ok(source) => source,
+        Err(err) => {
+            eprintln!("error: could not read program: {}", err);
+            return EXIT_NO_INPUT;
+        }
+    };
+    match session.run(&source) {
+        Ok(_) => 0,
+        Err(err) => {
+            let color = io::stderr().is_terminal();
+            eprint!("{}", err.render(&source, color));
+            err.exit_code()
+        }
+    }
 }
@@ -1,25 +1,151 @@
 use core::fmt;
+use std::collections::BTreeMap;
 use std::rc::Rc;
 
+use num_bigint::BigInt;
+
+use crate::ast::ast::BlockStatement;
+use crate::code::Instructions;
+use crate::eval::environment::Env;
+use crate::eval::EvaluatorResult;
+use crate::span::Span;
+
+pub type BuiltinFunction = fn(Vec<Rc<Object>>) -> EvaluatorResult;
+
+// Builtins are unique per name, so comparing their function pointers is sound here.
+#[allow(unpredictable_function_pointer_comparisons)]
 #[derive(Debug, Clone, PartialEq)]
 pub enum Object {
-    Integer(i32),
+    Integer(i64),
+    /// An integer outside the `i64` range. Arithmetic promotes to this on
+    /// overflow and narrows back to `Integer` once the value fits again.
+    BigInteger(BigInt),
+    Float(f64),
     Boolean(bool),
     String(String),
     Null,
     ReturnValue(Rc<Object>),
     Error(String),
+    Function(Vec<String>, BlockStatement, Env),
+    Array(Vec<Rc<Object>>),
+    Hash(BTreeMap<HashKey, (Rc<Object>, Rc<Object>)>),
+    /// The half-open integer range `start..end`, iterated lazily.
+    Range(i64, i64),
+    Builtin(&'static str, BuiltinFunction),
+    /// A function compiled for the VM, with the values of the free variables
+    /// it captured when it was created.
+    Closure(Rc<CompiledFunction>, Vec<Rc<Object>>),
+}
+
+/// The bytecode of a function literal, see `compiler::Compiler`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompiledFunction {
+    pub instructions: Instructions,
+    /// Source locations of the instructions that can fail at runtime.
+    pub spans: Vec<(usize, Span)>,
+    pub parameters: Vec<String>,
+    /// How many local slots a call needs, parameters included.
+    pub num_locals: usize,
+    /// The name the function was bound to by `let`, if any.
+    pub name: Option<String>,
+}
+
+impl CompiledFunction {
+    /// The source location of the instruction at `ip`.
+    pub fn span_at(&self, ip: usize) -> Option<Span> {
+        self.spans
+            .iter()
+            .rev()
+            .find(|(offset, _)| *offset <= ip)
+            .map(|(_, span)| *span)
+    }
+}
+
+/// The subset of objects that can be used as keys in an `Object::Hash`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum HashKey {
+    Integer(i64),
+    BigInteger(BigInt),
+    Boolean(bool),
+    String(String),
+}
+
+impl Object {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Object::Integer(_) | Object::BigInteger(_) => "INTEGER",
+            Object::Float(_) => "FLOAT",
+            Object::Boolean(_) => "BOOLEAN",
+            Object::String(_) => "STRING",
+            Object::Null => "NULL",
+            Object::ReturnValue(_) => "RETURN_VALUE",
+            Object::Error(_) => "ERROR",
+            Object::Function(..) | Object::Closure(..) => "FUNCTION",
+            Object::Array(_) => "ARRAY",
+            Object::Hash(_) => "HASH",
+            Object::Range(..) => "RANGE",
+            Object::Builtin(..) => "BUILTIN",
+        }
+    }
+
+    /// Returns the key this object hashes to, or `None` if it is not hashable.
+    pub fn hash_key(&self) -> Option<HashKey> {
+        match self {
+            Object::Integer(i) => Some(HashKey::Integer(*i)),
+            Object::BigInteger(b) => Some(HashKey::BigInteger(b.clone())),
+            Object::Boolean(b) => Some(HashKey::Boolean(*b)),
+            Object::String(s) => Some(HashKey::String(s.clone())),
+            _ => None,
+        }
+    }
+}
+
+impl From<BigInt> for Object {
+    fn from(value: BigInt) -> Self {
+        match i64::try_from(&value) {
+            Ok(i) => Object::Integer(i),
+            Err(_) => Object::BigInteger(value),
+        }
+    }
 }
 
 impl fmt::Display for Object {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
             Object::Integer(i) => write!(f, "{}", i),
+            Object::BigInteger(b) => write!(f, "{}", b),
+            // Debug formatting is the shortest form that parses back to the
+            // same value and always keeps a `.` or exponent, e.g. `1.0`.
+            Object::Float(x) => write!(f, "{:?}", x),
             Object::Boolean(b) => write!(f, "{}", b),
             Object::Null => write!(f, "null"),
             Object::ReturnValue(obj) => write!(f, "{}", obj),
             Object::Error(e) => write!(f, "Error: {}", e),
             Object::String(s) => write!(f, "{}", s),
+            Object::Function(params, _body, _env) => {
+                write!(f, "fn({}) {{...}}", params.join(", "))
+            }
+            Object::Array(elements) => {
+                write!(f, "[")?;
+                for (i, element) in elements.iter().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{}", separator, element)?;
+                }
+                write!(f, "]")
+            }
+            Object::Hash(pairs) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in pairs.values().enumerate() {
+                    let separator = if i == 0 { "" } else { ", " };
+                    write!(f, "{}{}: {}", separator, key, value)?;
+                }
+                write!(f, "}}")
+            }
+            Object::Range(start, end) => write!(f, "{}..{}", start, end),
+            Object::Builtin(name, _) => write!(f, "builtin function {}", name),
+            Object::Closure(function, _) => {
+                write!(f, "fn({}) {{...}}", function.parameters.join(", "))
+            }
         }
     }
 }
@@ -1,18 +1,119 @@
 use std::fmt;
 
+use crate::ast::ast::Expression;
+use crate::lexer::error::{LexerError, LexerErrorKind};
+use crate::span::Span;
+use crate::token::token::Token;
+
 pub type ParserErrors = Vec<ParserError>;
 
 #[derive(Debug, Clone)]
-pub struct ParserError(String);
+pub struct ParserError {
+    kind: ParserErrorKind,
+    span: Span,
+}
+
+/// What went wrong while parsing. Every kind has a stable code in the
+/// `E01xx` range, see [`ParserErrorKind::code`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParserErrorKind {
+    /// An error reported by the lexer, such as an unterminated string. These
+    /// keep the lexer's own `E00xx` codes.
+    Lexer(LexerErrorKind),
+    UnexpectedToken {
+        expected: Token,
+        found: Token,
+    },
+    /// A `let` or `for` that is not followed by a name.
+    MissingIdentifier {
+        found: Token,
+    },
+    /// A function parameter that is not a name.
+    InvalidParameter {
+        found: Token,
+    },
+    /// A token that cannot start an expression.
+    NoPrefixParse(Token),
+    InvalidAssignmentTarget(Box<Expression>),
+    /// A `break` or `continue` outside of a loop body.
+    LoopControlOutsideLoop(Token),
+    /// Expressions nested deeper than the parser's limit, or than its stack
+    /// budget allows.
+    NestingTooDeep(usize),
+}
+
+impl ParserErrorKind {
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParserErrorKind::Lexer(kind) => kind.code(),
+            ParserErrorKind::UnexpectedToken { .. } => "E0101",
+            ParserErrorKind::MissingIdentifier { .. } => "E0102",
+            ParserErrorKind::InvalidParameter { .. } => "E0103",
+            ParserErrorKind::NoPrefixParse(_) => "E0104",
+            ParserErrorKind::InvalidAssignmentTarget(_) => "E0105",
+            ParserErrorKind::LoopControlOutsideLoop(_) => "E0106",
+            ParserErrorKind::NestingTooDeep(_) => "E0107",
+        }
+    }
+}
+
+impl fmt::Display for ParserErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParserErrorKind::Lexer(kind) => write!(f, "{}", kind),
+            ParserErrorKind::UnexpectedToken { expected, found } => write!(
+                f,
+                "expected next token to be {}, but got {} instead",
+                expected, found
+            ),
+            ParserErrorKind::MissingIdentifier { found } => {
+                write!(f, "expected next token to be IDENT, got {} instead", found)
+            }
+            ParserErrorKind::InvalidParameter { found } => {
+                write!(f, "expected identifier, got {:?}", found)
+            }
+            ParserErrorKind::NoPrefixParse(token) => {
+                write!(f, "no prefix parse function for {:?} found", token)
+            }
+            ParserErrorKind::InvalidAssignmentTarget(target) => {
+                write!(f, "invalid assignment target: {}", target)
+            }
+            ParserErrorKind::LoopControlOutsideLoop(keyword) => {
+                write!(f, "`{}` outside of a loop", keyword)
+            }
+            ParserErrorKind::NestingTooDeep(limit) => {
+                write!(f, "expression nested too deeply: the limit is {}", limit)
+            }
+        }
+    }
+}
 
 impl fmt::Display for ParserError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{}", self.0)
+        write!(f, "{}", self.kind)
     }
 }
 
 impl ParserError {
-    pub fn new(msg: String) -> Self {
-        ParserError(msg)
+    pub fn new(kind: ParserErrorKind, span: Span) -> Self {
+        ParserError { kind, span }
+    }
+
+    pub fn kind(&self) -> &ParserErrorKind {
+        &self.kind
+    }
+
+    pub fn code(&self) -> &'static str {
+        self.kind.code()
+    }
+
+    pub fn span(&self) -> Span {
+        self.span
+    }
+}
+
+impl From<LexerError> for ParserError {
+    fn from(err: LexerError) -> Self {
+        ParserError::new(ParserErrorKind::Lexer(err.kind().clone()), err.span())
     }
 }
@@ -4,21 +4,35 @@ pub mod precedence;
 pub mod parser {
 
     use super::error::ParserError;
+    use super::error::ParserErrorKind;
     use super::error::ParserErrors;
     use super::precedence;
     use super::precedence::Precedence;
     use crate::ast::ast::BlockStatement;
     use crate::ast::ast::Expression;
+    use crate::ast::ast::ExpressionKind;
     use crate::ast::ast::Literal;
     use crate::ast::ast::Node;
     use crate::ast::ast::Statement;
+    use crate::ast::ast::StatementKind;
     use crate::lexer::lexer::Lexer;
+    use crate::span::Span;
+    use crate::stack::{StackGuard, DEFAULT_MAX_STACK};
 
     use crate::token::token::Token;
 
+    /// How deeply expressions may nest before parsing fails, unless a
+    /// different limit is given with [`Parser::with_max_depth`].
+    pub const DEFAULT_MAX_DEPTH: usize = 256;
+
     pub fn parse(input: &str) -> Result<Node, ParserErrors> {
+        parse_with_max_depth(input, DEFAULT_MAX_DEPTH)
+    }
+
+    /// Parses `input`, rejecting expressions nested more than `max_depth` deep.
+    pub fn parse_with_max_depth(input: &str, max_depth: usize) -> Result<Node, ParserErrors> {
         let lexer = Lexer::new(input);
-        let mut parser = Parser::new(lexer);
+        let mut parser = Parser::new(lexer).with_max_depth(max_depth);
         let program = parser.parse_program()?;
 
         Ok(Node::Program(program))
@@ -27,8 +41,20 @@ pub mod parser {
     pub struct Parser {
         pub l: Lexer,
         pub cur_token: Token,
+        pub cur_span: Span,
         pub peek_token: Token,
+        pub peek_span: Span,
         pub errors: Vec<ParserError>,
+        /// How many loop bodies enclose the current token, so `break` and
+        /// `continue` can be rejected outside of them.
+        loop_depth: usize,
+        /// How many `{` enclose `cur_token`. A `{` or `}` counts as outside
+        /// the braces it delimits. Used to resynchronise after an error.
+        brace_depth: usize,
+        /// How many expressions are being parsed around the current one.
+        depth: usize,
+        max_depth: usize,
+        stack: StackGuard,
     }
 
     impl Parser {
@@ -36,8 +62,15 @@ pub mod parser {
             let mut p = Parser {
                 l,
                 cur_token: Token::Eof,
+                cur_span: Span::default(),
                 peek_token: Token::Eof,
+                peek_span: Span::default(),
                 errors: Vec::new(),
+                loop_depth: 0,
+                brace_depth: 0,
+                depth: 0,
+                max_depth: DEFAULT_MAX_DEPTH,
+                stack: StackGuard::new(DEFAULT_MAX_STACK),
             };
             p.consume();
             p.consume();
@@ -48,36 +81,83 @@ pub mod parser {
             self.errors.clone()
         }
 
-        fn peek_error(&mut self, t: &Token) {
-            let msg = format!(
-                "expected next token to be {}, got {} instead",
-                t, self.peek_token
-            );
-            self.errors.push(ParserError::new(msg));
+        /// Limits how deeply expressions may nest, so that pathological input
+        /// is reported as an error instead of overflowing the stack.
+        pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+            self.max_depth = max_depth;
+            self
         }
 
+        /// Limits how many bytes of stack parsing may use, measured from the
+        /// caller's frame. Running out reports the depth reached as the limit.
+        pub fn with_max_stack(mut self, max_stack: usize) -> Self {
+            self.stack = StackGuard::new(max_stack);
+            self
+        }
+
+        /// Advances to the next token. Lexer errors are recorded and parsing
+        /// carries on with the token the lexer recovered.
         fn consume(&mut self) {
-            self.cur_token = self.peek_token.clone();
-            self.peek_token = self.l.next_token().unwrap();
+            let next = self.l.next_spanned_token().unwrap_or_else(|err| {
+                let recovered = err.recovered().clone();
+                self.errors.push(err.into());
+                recovered
+            });
+            if self.cur_token_is(&Token::LBrace) {
+                self.brace_depth += 1;
+            }
+            self.cur_token = std::mem::replace(&mut self.peek_token, next.token);
+            self.cur_span = std::mem::replace(&mut self.peek_span, next.span);
+            if self.cur_token_is(&Token::RBrace) {
+                self.brace_depth = self.brace_depth.saturating_sub(1);
+            }
         }
 
-        fn error_no_identifier(&mut self) -> ParserError {
-            let msg = format!(
-                "expected next token to be IDENT, got {} instead",
-                self.peek_token
-            );
-            ParserError::new(msg)
+        /// Skips the rest of a statement that failed to parse, so that the
+        /// next statement can be parsed on its own. Stops after a `;`, at a
+        /// statement keyword, or at the `}` closing the enclosing block, taking
+        /// only tokens at the statement's own brace `depth` into account.
+        fn synchronize(&mut self, depth: usize) {
+            loop {
+                if self.cur_token_is(&Token::Eof) || self.brace_depth < depth {
+                    return;
+                }
+                if self.brace_depth == depth && self.cur_token_is(&Token::Semicolon) {
+                    self.consume();
+                    return;
+                }
+                self.consume();
+                if self.brace_depth == depth && starts_statement(&self.cur_token) {
+                    return;
+                }
+            }
+        }
+
+        fn error_no_identifier(&self) -> ParserError {
+            let found = self.peek_token.clone();
+            ParserError::new(ParserErrorKind::MissingIdentifier { found }, self.peek_span)
+        }
+
+        fn error_invalid_parameter(&self) -> ParserError {
+            let found = self.cur_token.clone();
+            ParserError::new(ParserErrorKind::InvalidParameter { found }, self.cur_span)
         }
 
         pub fn parse_program(&mut self) -> Result<Vec<Statement>, ParserErrors> {
             let mut program = vec![];
 
             while self.cur_token != Token::Eof {
+                let depth = self.brace_depth;
                 match self.parse_statement() {
-                    Ok(stmt) => program.push(stmt),
-                    Err(e) => self.errors.push(e),
+                    Ok(stmt) => {
+                        program.push(stmt);
+                        self.consume();
+                    }
+                    Err(e) => {
+                        self.errors.push(e);
+                        self.synchronize(depth);
+                    }
                 }
-                self.consume();
             }
             if !self.errors.is_empty() {
                 Err(self.errors.clone())
@@ -87,14 +167,18 @@ pub mod parser {
         }
 
         fn parse_statement(&mut self) -> Result<Statement, ParserError> {
-            match self.cur_token {
-                Token::Let => self.parse_let_statement(),
-                Token::Return => self.parse_return_statement(),
-                _ => self.parse_expression_statement(),
-            }
+            let start = self.cur_span;
+            let kind = match self.cur_token {
+                Token::Let => self.parse_let_statement()?,
+                Token::Return => self.parse_return_statement()?,
+                Token::Break => self.parse_loop_control_statement(StatementKind::Break)?,
+                Token::Continue => self.parse_loop_control_statement(StatementKind::Continue)?,
+                _ => self.parse_expression_statement()?,
+            };
+            Ok(Statement::new(kind, start.to(self.cur_span)))
         }
 
-        fn parse_let_statement(&mut self) -> Result<Statement, ParserError> {
+        fn parse_let_statement(&mut self) -> Result<StatementKind, ParserError> {
             let ident = match &self.peek_token {
                 Token::Ident(ref s) => s.clone(),
                 _t => {
@@ -108,11 +192,14 @@ pub mod parser {
 
             let expr = self.parse_expression(Precedence::Lowest)?;
 
-            while !self.cur_token_is(&Token::Semicolon) {
+            // Stop at the end of the expression rather than skipping ahead to
+            // the next `;`, so a missing semicolon cannot swallow the closing
+            // brace or the statements after it.
+            if self.peek_token_is(&Token::Semicolon) {
                 self.consume();
             }
 
-            Ok(Statement::Let(ident, expr))
+            Ok(StatementKind::Let(ident, expr))
         }
 
         fn cur_token_is(&self, t: &Token) -> bool {
@@ -128,95 +215,157 @@ pub mod parser {
                 self.consume();
                 Ok(())
             } else {
-                Err(ParserError::new(format!(
-                    "expected next token to be {}, but got {} instead",
-                    t, self.peek_token
-                )))
+                Err(ParserError::new(
+                    ParserErrorKind::UnexpectedToken {
+                        expected: t.clone(),
+                        found: self.peek_token.clone(),
+                    },
+                    self.peek_span,
+                ))
             }
         }
 
-        fn parse_return_statement(&mut self) -> Result<Statement, ParserError> {
+        fn parse_return_statement(&mut self) -> Result<StatementKind, ParserError> {
             self.consume();
 
             let expr = self.parse_expression(Precedence::Lowest)?;
 
-            while !self.cur_token_is(&Token::Semicolon) {
+            if self.peek_token_is(&Token::Semicolon) {
                 self.consume();
             }
 
-            Ok(Statement::Return(expr))
+            Ok(StatementKind::Return(expr))
         }
 
-        fn parse_expression_statement(&mut self) -> Result<Statement, ParserError> {
+        fn parse_loop_control_statement(
+            &mut self,
+            kind: StatementKind,
+        ) -> Result<StatementKind, ParserError> {
+            let (keyword, span) = (self.cur_token.clone(), self.cur_span);
+            if self.peek_token_is(&Token::Semicolon) {
+                self.consume();
+            }
+            if self.loop_depth == 0 {
+                return Err(ParserError::new(
+                    ParserErrorKind::LoopControlOutsideLoop(keyword),
+                    span,
+                ));
+            }
+            Ok(kind)
+        }
+
+        fn parse_expression_statement(&mut self) -> Result<StatementKind, ParserError> {
             let expr = self.parse_expression(Precedence::Lowest)?;
 
             if self.peek_token_is(&Token::Semicolon) {
                 self.consume();
             }
 
-            Ok(Statement::Expr(expr))
+            Ok(StatementKind::Expr(expr))
         }
 
         fn parse_expression(&mut self, precedence: Precedence) -> Result<Expression, ParserError> {
-            let mut left_expr = match self.cur_token {
+            if self.depth >= self.max_depth || self.stack.exhausted() {
+                return Err(ParserError::new(
+                    ParserErrorKind::NestingTooDeep(self.depth),
+                    self.cur_span,
+                ));
+            }
+            self.depth += 1;
+            let expr = self.parse_nested_expression(precedence);
+            self.depth -= 1;
+            expr
+        }
+
+        fn parse_nested_expression(
+            &mut self,
+            precedence: Precedence,
+        ) -> Result<Expression, ParserError> {
+            let start = self.cur_span;
+            let left_kind = match self.cur_token {
                 Token::Bang | Token::Minus => self.parse_prefix_expression(),
-                Token::Ident(ref id) => Ok(Expression::Identifier(id.clone())),
-                Token::Int(i) => Ok(Expression::Lit(Literal::Int(i))),
-                Token::String(ref s) => Ok(Expression::Lit(Literal::String(s.clone()))),
-                Token::Boolean(b) => Ok(Expression::Lit(Literal::Bool(b))),
+                Token::Ident(ref id) => Ok(ExpressionKind::Identifier(id.clone())),
+                Token::Int(i) => Ok(ExpressionKind::Lit(Literal::Int(i))),
+                Token::BigInt(ref b) => Ok(ExpressionKind::Lit(Literal::BigInt(b.clone()))),
+                Token::Float(x) => Ok(ExpressionKind::Lit(Literal::Float(x))),
+                Token::String(ref s) => Ok(ExpressionKind::Lit(Literal::String(s.clone()))),
+                Token::Boolean(b) => Ok(ExpressionKind::Lit(Literal::Bool(b))),
                 Token::LParen => {
                     self.consume();
                     let expr = self.parse_expression(Precedence::Lowest)?;
                     self.expect_peek(&Token::RParen)?;
-                    Ok(expr)
+                    Ok(expr.kind)
                 }
+                Token::LBracket => {
+                    let elements = self.parse_expression_list(&Token::RBracket)?;
+                    Ok(ExpressionKind::Lit(Literal::Array(elements)))
+                }
+                Token::LBrace => self.parse_hash_literal(),
                 Token::If => self.parse_if_expression(),
+                Token::While => self.parse_while_expression(),
+                Token::For => self.parse_for_expression(),
                 Token::Fn => self.parse_function_literal(),
-                _ => Err(ParserError::new(format!(
-                    "no prefix parse function for {:?} found",
-                    self.cur_token
-                ))),
+                _ => Err(ParserError::new(
+                    ParserErrorKind::NoPrefixParse(self.cur_token.clone()),
+                    self.cur_span,
+                )),
             };
+            let mut left_expr = Expression::new(left_kind?, start.to(self.cur_span));
 
             while !self.peek_token_is(&Token::Semicolon)
                 && precedence < self.next_token_precedence()
             {
-                match self.peek_token {
+                let kind = match self.peek_token {
                     Token::Plus
                     | Token::Minus
                     | Token::Slash
                     | Token::Asterisk
+                    | Token::Percent
+                    | Token::Power
                     | Token::Eq
                     | Token::NotEq
                     | Token::Lt
-                    | Token::Gt => {
+                    | Token::Gt
+                    | Token::LtEq
+                    | Token::GtEq
+                    | Token::And
+                    | Token::Or => {
                         self.consume();
-                        let expr = left_expr.unwrap();
-                        left_expr = self.parse_infix_expression(expr);
+                        self.parse_infix_expression(left_expr)?
                     }
-                    // Token::LBracket => {
-                    //     self.next_token();
-                    //     let expr = left_expr.unwrap();
-                    //     left_expr = self.parse_index_expression(expr);
-                    // }
-                    Token::LParen => {
+                    Token::Assign
+                    | Token::PlusAssign
+                    | Token::MinusAssign
+                    | Token::AsteriskAssign
+                    | Token::SlashAssign
+                    | Token::PercentAssign => {
+                        self.consume();
+                        self.parse_assign_expression(left_expr)?
+                    }
+                    Token::DotDot => {
                         self.consume();
-                        let expr = left_expr.unwrap();
-                        left_expr = self.parse_call_expression(expr);
+                        self.parse_range_expression(left_expr)?
                     }
-                    _ => {
-                        return left_expr;
+                    Token::LBracket => {
+                        self.consume();
+                        self.parse_index_expression(left_expr)?
                     }
-                }
+                    Token::LParen => {
+                        self.consume();
+                        self.parse_call_expression(left_expr)?
+                    }
+                    _ => break,
+                };
+                left_expr = Expression::new(kind, start.to(self.cur_span));
             }
-            left_expr
+            Ok(left_expr)
         }
 
-        fn parse_prefix_expression(&mut self) -> Result<Expression, ParserError> {
+        fn parse_prefix_expression(&mut self) -> Result<ExpressionKind, ParserError> {
             let prefix = self.cur_token.clone();
             self.consume();
             let expr = self.parse_expression(Precedence::Prefix)?;
-            Ok(Expression::Prefix(prefix, Box::new(expr)))
+            Ok(ExpressionKind::Prefix(prefix, Box::new(expr)))
         }
 
         fn next_token_precedence(&self) -> Precedence {
@@ -226,19 +375,58 @@ pub mod parser {
         fn parse_infix_expression(
             &mut self,
             left_expr: Expression,
-        ) -> Result<Expression, ParserError> {
+        ) -> Result<ExpressionKind, ParserError> {
             let infix_op = self.cur_token.clone();
             self.consume();
-            let precedence = precedence::token_to_precedence(&infix_op);
+            let precedence = match infix_op {
+                // Parsing the right operand one level lower makes `**` right-associative.
+                Token::Power => Precedence::Prefix,
+                _ => precedence::token_to_precedence(&infix_op),
+            };
             let right_expr = self.parse_expression(precedence)?;
-            Ok(Expression::Infix(
+            Ok(ExpressionKind::Infix(
                 infix_op,
                 Box::new(left_expr),
                 Box::new(right_expr),
             ))
         }
 
-        fn parse_if_expression(&mut self) -> Result<Expression, ParserError> {
+        fn parse_assign_expression(
+            &mut self,
+            target: Expression,
+        ) -> Result<ExpressionKind, ParserError> {
+            if !matches!(
+                target.kind,
+                ExpressionKind::Identifier(_) | ExpressionKind::Index(..)
+            ) {
+                let span = target.span;
+                return Err(ParserError::new(
+                    ParserErrorKind::InvalidAssignmentTarget(Box::new(target)),
+                    span,
+                ));
+            }
+            let op = self.cur_token.clone();
+            self.consume();
+            // Parsing the value at the lowest precedence makes `a = b = c`
+            // right-associative.
+            let value = self.parse_expression(Precedence::Lowest)?;
+            Ok(ExpressionKind::Assign(
+                op,
+                Box::new(target),
+                Box::new(value),
+            ))
+        }
+
+        fn parse_range_expression(
+            &mut self,
+            start: Expression,
+        ) -> Result<ExpressionKind, ParserError> {
+            self.consume();
+            let end = self.parse_expression(Precedence::Range)?;
+            Ok(ExpressionKind::Range(Box::new(start), Box::new(end)))
+        }
+
+        fn parse_if_expression(&mut self) -> Result<ExpressionKind, ParserError> {
             self.expect_peek(&Token::LParen)?;
             self.consume();
             let condition = self.parse_expression(Precedence::Lowest)?;
@@ -252,32 +440,73 @@ pub mod parser {
             } else {
                 None
             };
-            Ok(Expression::If(
+            Ok(ExpressionKind::If(
                 Box::new(condition),
                 consequence,
                 alternative,
             ))
         }
 
+        fn parse_while_expression(&mut self) -> Result<ExpressionKind, ParserError> {
+            self.expect_peek(&Token::LParen)?;
+            self.consume();
+            let condition = self.parse_expression(Precedence::Lowest)?;
+            self.expect_peek(&Token::RParen)?;
+            self.expect_peek(&Token::LBrace)?;
+            self.loop_depth += 1;
+            let body = self.parse_block_statement();
+            self.loop_depth -= 1;
+            Ok(ExpressionKind::While(Box::new(condition), body?))
+        }
+
+        fn parse_for_expression(&mut self) -> Result<ExpressionKind, ParserError> {
+            self.expect_peek(&Token::LParen)?;
+            let name = match &self.peek_token {
+                Token::Ident(name) => name.clone(),
+                _ => return Err(self.error_no_identifier()),
+            };
+            self.consume();
+            self.expect_peek(&Token::In)?;
+            self.consume();
+            let iterable = self.parse_expression(Precedence::Lowest)?;
+            self.expect_peek(&Token::RParen)?;
+            self.expect_peek(&Token::LBrace)?;
+            self.loop_depth += 1;
+            let body = self.parse_block_statement();
+            self.loop_depth -= 1;
+            Ok(ExpressionKind::For(name, Box::new(iterable), body?))
+        }
+
         fn parse_block_statement(&mut self) -> Result<BlockStatement, ParserError> {
             self.consume();
             let mut block_statement = Vec::new();
 
             while !self.cur_token_is(&Token::RBrace) && !self.cur_token_is(&Token::Eof) {
-                if let Ok(stmt) = self.parse_statement() {
-                    block_statement.push(stmt);
+                let depth = self.brace_depth;
+                match self.parse_statement() {
+                    Ok(stmt) => {
+                        block_statement.push(stmt);
+                        self.consume();
+                    }
+                    Err(e) => {
+                        self.errors.push(e);
+                        self.synchronize(depth);
+                    }
                 }
-                self.consume();
             }
             Ok(block_statement)
         }
 
-        fn parse_function_literal(&mut self) -> Result<Expression, ParserError> {
+        fn parse_function_literal(&mut self) -> Result<ExpressionKind, ParserError> {
             self.expect_peek(&Token::LParen)?;
             let params = self.parse_function_parameters()?;
             self.expect_peek(&Token::LBrace)?;
-            let body = self.parse_block_statement()?;
-            Ok(Expression::Fn(params, body))
+            // A function body starts a fresh context: loops around the
+            // literal cannot be broken out of from inside it.
+            let loop_depth = std::mem::take(&mut self.loop_depth);
+            let body = self.parse_block_statement();
+            self.loop_depth = loop_depth;
+            Ok(ExpressionKind::Fn(params, body?))
         }
 
         fn parse_function_parameters(&mut self) -> Result<Vec<String>, ParserError> {
@@ -290,57 +519,98 @@ pub mod parser {
 
             match &self.cur_token {
                 Token::Ident(ref id) => params.push(id.clone()),
-                _ => {
-                    return Err(ParserError::new(format!(
-                        "expected identifier, got {:?}",
-                        self.cur_token
-                    )))
-                }
+                _ => return Err(self.error_invalid_parameter()),
             }
             while self.peek_token_is(&Token::Comma) {
                 self.consume();
                 self.consume();
                 match &self.cur_token {
                     Token::Ident(ref id) => params.push(id.clone()),
-                    _ => {
-                        return Err(ParserError::new(format!(
-                            "expected identifier, got {:?}",
-                            self.cur_token
-                        )))
-                    }
+                    _ => return Err(self.error_invalid_parameter()),
                 }
             }
             self.expect_peek(&Token::RParen)?;
             Ok(params)
         }
 
-        fn parse_call_expression(&mut self, expr: Expression) -> Result<Expression, ParserError> {
-            let arguments = self.parse_call_arguments()?;
-            Ok(Expression::Call(Box::new(expr), arguments))
+        fn parse_call_expression(
+            &mut self,
+            expr: Expression,
+        ) -> Result<ExpressionKind, ParserError> {
+            let arguments = self.parse_expression_list(&Token::RParen)?;
+            Ok(ExpressionKind::Call(Box::new(expr), arguments))
         }
 
-        fn parse_call_arguments(&mut self) -> Result<Vec<Expression>, ParserError> {
-            let mut args = Vec::new();
-            if self.peek_token_is(&Token::RParen) {
+        fn parse_index_expression(
+            &mut self,
+            expr: Expression,
+        ) -> Result<ExpressionKind, ParserError> {
+            self.consume();
+            let index = self.parse_expression(Precedence::Lowest)?;
+            self.expect_peek(&Token::RBracket)?;
+            Ok(ExpressionKind::Index(Box::new(expr), Box::new(index)))
+        }
+
+        fn parse_hash_literal(&mut self) -> Result<ExpressionKind, ParserError> {
+            let mut pairs = Vec::new();
+            while !self.peek_token_is(&Token::RBrace) {
                 self.consume();
-                return Ok(args);
+                let key = self.parse_expression(Precedence::Lowest)?;
+                self.expect_peek(&Token::Colon)?;
+                self.consume();
+                let value = self.parse_expression(Precedence::Lowest)?;
+                pairs.push((key, value));
+                if !self.peek_token_is(&Token::RBrace) {
+                    self.expect_peek(&Token::Comma)?;
+                }
+            }
+            self.expect_peek(&Token::RBrace)?;
+            Ok(ExpressionKind::Lit(Literal::Hash(pairs)))
+        }
+
+        fn parse_expression_list(&mut self, end: &Token) -> Result<Vec<Expression>, ParserError> {
+            let mut list = Vec::new();
+            if self.peek_token_is(end) {
+                self.consume();
+                return Ok(list);
             }
             self.consume();
-            args.push(self.parse_expression(Precedence::Lowest)?);
+            list.push(self.parse_expression(Precedence::Lowest)?);
             while self.peek_token_is(&Token::Comma) {
                 self.consume();
                 self.consume();
-                args.push(self.parse_expression(Precedence::Lowest)?);
+                list.push(self.parse_expression(Precedence::Lowest)?);
             }
-            self.expect_peek(&Token::RParen)?;
-            Ok(args)
+            self.expect_peek(end)?;
+            Ok(list)
         }
     }
+
+    /// Tokens that begin a statement, where parsing can resume after an error.
+    fn starts_statement(token: &Token) -> bool {
+        matches!(
+            token,
+            Token::Let
+                | Token::Return
+                | Token::If
+                | Token::While
+                | Token::For
+                | Token::Break
+                | Token::Continue
+        )
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::parser::parse;
+    use super::error::ParserErrorKind;
+    use super::parser::{parse, parse_with_max_depth, Parser, DEFAULT_MAX_DEPTH};
+    use crate::ast::ast::{ExpressionKind, Node, StatementKind};
+    use crate::lexer::error::LexerErrorKind;
+    use crate::lexer::lexer::Lexer;
+    use crate::runner::{max_stack_for, with_stack_size, DEFAULT_STACK_SIZE};
+    use crate::span::Span;
+    use crate::token::token::Token;
 
     fn apply_test(test_case: &[(&str, &str)]) {
         for (input, expected) in test_case {
@@ -431,6 +701,19 @@ mod tests {
             ("3 + 4; -5 * 5", "(3 + 4)((-5) * 5)"),
             ("5 > 4 == 3 < 4", "((5 > 4) == (3 < 4))"),
             ("5 < 4 != 3 > 4", "((5 < 4) != (3 > 4))"),
+            ("a || b && c", "(a || (b && c))"),
+            ("a && b || c", "((a && b) || c)"),
+            ("a == b && c != d", "((a == b) && (c != d))"),
+            ("a < b || !c", "((a < b) || (!c))"),
+            ("a || b || c", "((a || b) || c)"),
+            ("a <= b == c >= d", "((a <= b) == (c >= d))"),
+            ("a + b % c * d", "(a + ((b % c) * d))"),
+            ("2 ** 3 ** 2", "(2 ** (3 ** 2))"),
+            ("2 * 3 ** 2", "(2 * (3 ** 2))"),
+            ("2 ** 3 * 2", "((2 ** 3) * 2)"),
+            ("-2 ** 2", "(-(2 ** 2))"),
+            ("2 ** -1", "(2 ** (-1))"),
+            ("a[0] ** f(x)", "((a[0]) ** f(x))"),
         ];
         apply_test(&test_case);
     }
@@ -481,6 +764,65 @@ mod tests {
         apply_test(&test_case);
     }
 
+    #[test]
+    fn test_while_expression() {
+        let test_case = [
+            ("while (x < y) { x }", "while (x < y) { x }"),
+            (
+                "while (true) { if (x) { break; } continue }",
+                "while true { if x { break; }continue; }",
+            ),
+        ];
+
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_for_expression() {
+        let test_case = [
+            ("for (x in xs) { x }", "for x in xs { x }"),
+            (
+                "for (i in 0..n + 1) { break; }",
+                "for i in (0..(n + 1)) { break; }",
+            ),
+            ("a < 0..3", "(a < (0..3))"),
+        ];
+
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_assign_expression() {
+        let test_case = [
+            ("x = y = 1", "(x = (y = 1))"),
+            ("x += 1 * 2", "(x += (1 * 2))"),
+            ("a[0] = b || c", "((a[0]) = (b || c))"),
+        ];
+
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_invalid_assignment_target() {
+        let errors = parse("x + 1 = 2").expect_err("invalid target");
+        assert_eq!(errors[0].to_string(), "invalid assignment target: (x + 1)");
+        assert_eq!(errors[0].span(), Span::new(0, 5, 1, 1));
+    }
+
+    #[test]
+    fn test_loop_control_outside_loop() {
+        let errors = parse("break; if (x) { continue; }").expect_err("outside loop");
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            ["`break` outside of a loop", "`continue` outside of a loop"]
+        );
+        assert_eq!(errors[1].span(), Span::new(16, 24, 1, 17));
+
+        let errors = parse("while (true) { fn() { break; } }").expect_err("inside fn");
+        assert_eq!(errors[0].to_string(), "`break` outside of a loop");
+    }
+
     #[test]
     fn test_function_literal_parsing() {
         let test_case = [
@@ -498,4 +840,251 @@ mod tests {
 
         apply_test(&test_case);
     }
+
+    #[test]
+    fn test_array_literal_parsing() {
+        let test_case = [("[]", "[]"), ("[1, 2 * 2, 3 + 3]", "[1, (2 * 2), (3 + 3)]")];
+
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_hash_literal_parsing() {
+        let test_case = [
+            ("{}", "{}"),
+            (r#"{"one": 1, "two": 2}"#, "{one: 1, two: 2}"),
+            (
+                r#"{"one": 0 + 1, "two": 10 - 8, "three": 15 / 5}"#,
+                "{one: (0 + 1), two: (10 - 8), three: (15 / 5)}",
+            ),
+            ("{true: 1, 2: x}", "{true: 1, 2: x}"),
+        ];
+
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_index_expression_parsing() {
+        let test_case = [
+            ("myArray[1 + 1]", "(myArray[(1 + 1)])"),
+            (
+                "a * [1, 2, 3, 4][b * c] * d",
+                "((a * ([1, 2, 3, 4][(b * c)])) * d)",
+            ),
+            (
+                "add(a * b[2], b[1], 2 * [1, 2][1])",
+                "add((a * (b[2])), (b[1]), (2 * ([1, 2][1])))",
+            ),
+        ];
+
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_let_statement_without_semicolon() {
+        let test_case = [
+            ("let x = 5", "let x = 5;"),
+            ("let x = 5 x", "let x = 5;x"),
+            ("return 1 2", "return 1;2"),
+        ];
+
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_statement_and_expression_spans() {
+        let input = "let x = 1;\nadd(x, 2 * 3);";
+        let stmts = match parse(input).expect("parse") {
+            Node::Program(stmts) => stmts,
+            node => panic!("expected a program, got {:?}", node),
+        };
+
+        assert_eq!(stmts[0].span, Span::new(0, 10, 1, 1));
+        assert_eq!(stmts[1].span, Span::new(11, 25, 2, 1));
+
+        let call = match &stmts[1].kind {
+            StatementKind::Expr(expr) => expr,
+            kind => panic!("expected an expression statement, got {:?}", kind),
+        };
+        assert_eq!(call.span, Span::new(11, 24, 2, 1));
+
+        let args = match &call.kind {
+            ExpressionKind::Call(_, args) => args,
+            kind => panic!("expected a call, got {:?}", kind),
+        };
+        assert_eq!(args[0].span, Span::new(15, 16, 2, 5));
+        assert_eq!(args[1].span, Span::new(18, 23, 2, 8));
+    }
+
+    #[test]
+    fn test_string_literal_escapes() {
+        let node = parse(r#"let s = "tab\there\n";"#).expect("parse");
+        assert_eq!(node.to_string(), "let s = tab\there\n;");
+    }
+
+    #[test]
+    fn test_lexer_errors_are_reported() {
+        let errors = parse(r#"puts("a\qb"); let s = "open"#).expect_err("lexer errors");
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(
+            messages,
+            vec!["invalid escape sequence: \\q", "unterminated string"]
+        );
+        assert_eq!(errors[0].span(), Span::new(7, 9, 1, 8));
+        assert_eq!(errors[1].span(), Span::new(22, 27, 1, 23));
+        assert_eq!(
+            errors[0].kind(),
+            &ParserErrorKind::Lexer(LexerErrorKind::InvalidEscape('q'))
+        );
+        assert_eq!(
+            errors[1].kind(),
+            &ParserErrorKind::Lexer(LexerErrorKind::UnterminatedString)
+        );
+        let codes: Vec<&str> = errors.iter().map(|e| e.code()).collect();
+        assert_eq!(codes, ["E0003", "E0001"]);
+    }
+
+    fn error_messages(input: &str) -> Vec<String> {
+        let errors = parse(input).expect_err("parse errors");
+        errors.iter().map(|e| e.to_string()).collect()
+    }
+
+    #[test]
+    fn test_error_recovery_reports_every_statement() {
+        assert_eq!(
+            error_messages("let = 1; let y 2; x + ; let ok = 1;"),
+            [
+                "expected next token to be IDENT, got = instead",
+                "expected next token to be =, but got 2 instead",
+                "no prefix parse function for Semicolon found",
+            ]
+        );
+        assert_eq!(
+            error_messages("let h = {1 2}; let w 3;"),
+            [
+                "expected next token to be :, but got 2 instead",
+                "expected next token to be =, but got 3 instead",
+            ]
+        );
+        assert_eq!(
+            error_messages("if (x { a; b } let c = ;"),
+            [
+                "expected next token to be ), but got { instead",
+                "no prefix parse function for Semicolon found",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_error_recovery_inside_blocks() {
+        assert_eq!(
+            error_messages("fn() { let = 1; 1 + }; let z 3"),
+            [
+                "expected next token to be IDENT, got = instead",
+                "no prefix parse function for RBrace found",
+                "expected next token to be =, but got 3 instead",
+            ]
+        );
+        assert_eq!(
+            error_messages("let f = fn(x) { x +* 2; let y = 3 } let 5"),
+            [
+                "no prefix parse function for Asterisk found",
+                "expected next token to be IDENT, got 5 instead",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_failed_prefix_before_infix_operator() {
+        assert_eq!(
+            error_messages("let x = ) + 1; let y = 2;"),
+            ["no prefix parse function for RParen found"]
+        );
+        assert_eq!(
+            error_messages("} let a = 1; }"),
+            [
+                "no prefix parse function for RBrace found",
+                "no prefix parse function for RBrace found",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nesting_limit() {
+        let nested = |depth: usize| format!("{}1{}", "(".repeat(depth), ")".repeat(depth));
+        // Unoptimised builds need more stack than the default allows to reach
+        // the default depth.
+        let parse_deep = |input: &str| {
+            with_stack_size(DEFAULT_STACK_SIZE, || {
+                Parser::new(Lexer::new(input))
+                    .with_max_stack(max_stack_for(DEFAULT_STACK_SIZE))
+                    .parse_program()
+            })
+            .expect("spawn")
+        };
+
+        assert!(parse_deep(&nested(DEFAULT_MAX_DEPTH - 1)).is_ok());
+        let errors = parse_deep(&nested(DEFAULT_MAX_DEPTH)).expect_err("too deep");
+        assert_eq!(errors.len(), 1);
+        assert_eq!(
+            errors[0].kind(),
+            &ParserErrorKind::NestingTooDeep(DEFAULT_MAX_DEPTH)
+        );
+        assert_eq!(errors[0].code(), "E0107");
+        assert_eq!(errors[0].span().column, DEFAULT_MAX_DEPTH + 1);
+
+        assert!(parse_with_max_depth("-(1 + 2)", 4).is_ok());
+        assert!(parse_with_max_depth("-(1 + 2)", 3).is_err());
+        let errors = parse_with_max_depth("let a = [[[1]]]; let b = 2;", 3).expect_err("too deep");
+        assert_eq!(errors.len(), 1);
+    }
+
+    #[test]
+    fn test_stack_limit() {
+        let input = format!("{}1", "-".repeat(100_000));
+        let errors = Parser::new(Lexer::new(&input))
+            .with_max_depth(usize::MAX)
+            .with_max_stack(64 * 1024)
+            .parse_program()
+            .expect_err("out of stack");
+        assert_eq!(errors.len(), 1);
+        match errors[0].kind() {
+            ParserErrorKind::NestingTooDeep(depth) => assert!(*depth < 100_000, "{}", depth),
+            kind => panic!("expected nesting too deep, got {:?}", kind),
+        }
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let errors = parse("let x = (1 + 2; let 5; break;").expect_err("errors");
+        assert_eq!(
+            errors[0].kind(),
+            &ParserErrorKind::UnexpectedToken {
+                expected: Token::RParen,
+                found: Token::Semicolon,
+            }
+        );
+        assert_eq!(
+            errors[1].kind(),
+            &ParserErrorKind::MissingIdentifier {
+                found: Token::Int(5)
+            }
+        );
+        assert_eq!(
+            errors[2].kind(),
+            &ParserErrorKind::LoopControlOutsideLoop(Token::Break)
+        );
+        let codes: Vec<&str> = errors.iter().map(|e| e.code()).collect();
+        assert_eq!(codes, ["E0101", "E0102", "E0106"]);
+    }
+
+    #[test]
+    fn test_parser_error_spans() {
+        let errors = parse("let x = (1 + 2;").expect_err("missing paren");
+        assert_eq!(errors[0].span(), Span::new(14, 15, 1, 15));
+        assert_eq!(
+            errors[0].to_string(),
+            "expected next token to be ), but got ; instead"
+        );
+    }
 }
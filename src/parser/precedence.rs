@@ -3,21 +3,36 @@ use crate::token::token::Token;
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
 pub enum Precedence {
     Lowest,
+    Assign,      // =, +=, -=, *=, /= or %=
+    LogicalOr,   // ||
+    LogicalAnd,  // &&
     Equals,      // == or !=
-    LessGreater, // > or <
+    LessGreater, // >, <, >= or <=
+    Range,       // ..
     Sum,         // + or -
-    Product,     // * or /
+    Product,     // *, / or %
     Prefix,      // -X or !X
+    Power,       // **
     Call,        // Function call
     Index,       // myArray[i]
 }
 
 pub fn token_to_precedence(t: &Token) -> Precedence {
     match t {
-        Token::Asterisk | Token::Slash => Precedence::Product,
+        Token::Power => Precedence::Power,
+        Token::Asterisk | Token::Slash | Token::Percent => Precedence::Product,
         Token::Plus | Token::Minus => Precedence::Sum,
-        Token::Lt | Token::Gt => Precedence::LessGreater,
+        Token::DotDot => Precedence::Range,
+        Token::Lt | Token::Gt | Token::LtEq | Token::GtEq => Precedence::LessGreater,
         Token::Eq | Token::NotEq => Precedence::Equals,
+        Token::And => Precedence::LogicalAnd,
+        Token::Or => Precedence::LogicalOr,
+        Token::Assign
+        | Token::PlusAssign
+        | Token::MinusAssign
+        | Token::AsteriskAssign
+        | Token::SlashAssign
+        | Token::PercentAssign => Precedence::Assign,
         Token::LParen => Precedence::Call,
         Token::LBracket => Precedence::Index,
         _ => Precedence::Lowest,
@@ -1,27 +1,23 @@
 pub mod repl {
 
-    use std::rc::Rc;
+    use std::io::IsTerminal;
 
-    use crate::eval::eval;
+    use crate::diagnostics::Diagnostic;
     use crate::parser::error::ParserError;
-    use crate::parser::parser::parse;
+    use crate::runner::{RunError, Session};
     use rustyline::error::ReadlineError;
     use rustyline::DefaultEditor;
 
-    pub fn start() -> rustyline::Result<()> {
+    pub fn start(mut session: Session) -> rustyline::Result<()> {
         let mut rl = DefaultEditor::new()?;
-        let env = Rc::new(Default::default());
+        let color = std::io::stdout().is_terminal();
         loop {
             let readline = rl.readline(">> ");
             match readline {
-                Ok(line) => match parse(&line) {
-                    Ok(node) => match eval(node, &Rc::clone(&env)) {
-                        Ok(evaluated) => println!("{}", evaluated),
-                        Err(e) => println!("Error: {}", e),
-                    },
-                    Err(e) => {
-                        print_parse_errors(e);
-                    }
+                Ok(line) => match session.run(&line) {
+                    Ok(evaluated) => println!("{}", evaluated),
+                    Err(RunError::Parse(errors)) => print_parse_errors(&line, errors, color),
+                    Err(err @ RunError::Runtime(_)) => print!("{}", err.render(&line, color)),
                 },
                 Err(ReadlineError::Interrupted) => {
                     println!("CTRL-C");
@@ -40,12 +36,12 @@ pub mod repl {
         Ok(())
     }
 
-    fn print_parse_errors(errors: Vec<ParserError>) {
+    fn print_parse_errors(source: &str, errors: Vec<ParserError>, color: bool) {
         println!(
             "Woops! We ran into some issues parsing your input, please fix the following errors:"
         );
         for err in errors {
-            println!("{}", err);
+            print!("{}", Diagnostic::from(&err).render(source, color));
         }
     }
 }
@@ -0,0 +1,398 @@
+use std::borrow::Cow;
+use std::cell::RefCell;
+use std::fmt;
+use std::mem;
+use std::rc::Rc;
+use std::str::FromStr;
+use std::{io, thread};
+
+use crate::ast::ast::Node;
+use crate::compiler::symbol_table::SymbolTable;
+use crate::compiler::Compiler;
+use crate::diagnostics::{render_traceback, Diagnostic};
+use crate::eval::environment::Env;
+use crate::eval::error::EvaluatorError;
+use crate::eval::eval_with;
+use crate::eval::limits::EvalConfig;
+use crate::lexer::lexer::Lexer;
+use crate::object::Object;
+use crate::parser::error::ParserErrors;
+use crate::parser::parser::{Parser, DEFAULT_MAX_DEPTH};
+use crate::vm::Vm;
+
+pub const EXIT_USAGE: i32 = 64;
+pub const EXIT_PARSE_ERROR: i32 = 65;
+pub const EXIT_NO_INPUT: i32 = 66;
+pub const EXIT_RUNTIME_ERROR: i32 = 70;
+
+/// Stack size for [`with_stack_size`] that leaves ample room for the default
+/// depth limits, even in unoptimised builds.
+pub const DEFAULT_STACK_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum RunError {
+    Parse(ParserErrors),
+    Runtime(EvaluatorError),
+}
+
+impl RunError {
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            RunError::Parse(_) => EXIT_PARSE_ERROR,
+            RunError::Runtime(_) => EXIT_RUNTIME_ERROR,
+        }
+    }
+
+    /// Renders every error as a diagnostic against `source`. Runtime errors
+    /// raised inside function calls are preceded by a traceback.
+    pub fn render(&self, source: &str, color: bool) -> String {
+        match self {
+            RunError::Parse(errors) => errors
+                .iter()
+                .map(|err| Diagnostic::from(err).render(source, color))
+                .collect(),
+            RunError::Runtime(err) => {
+                render_traceback(err, source, color) + &Diagnostic::from(err).render(source, color)
+            }
+        }
+    }
+}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::Parse(errors) => {
+                let messages = errors
+                    .iter()
+                    .map(|err| err.to_string())
+                    .collect::<Vec<String>>();
+                write!(f, "{}", messages.join("\n"))
+            }
+            RunError::Runtime(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+/// Blanks out a leading `#!` line so scripts can be executed directly. The
+/// line is replaced by spaces of the same length, so byte offsets, lines and
+/// columns in diagnostics still match the original source.
+pub fn strip_shebang(source: &str) -> Cow<'_, str> {
+    if source.starts_with("#!") {
+        let end = source.find('\n').unwrap_or(source.len());
+        Cow::Owned(" ".repeat(end) + &source[end..])
+    } else {
+        Cow::Borrowed(source)
+    }
+}
+
+/// Which engine executes parsed programs.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Backend {
+    /// The tree-walking evaluator in `eval`.
+    #[default]
+    Eval,
+    /// The bytecode compiler and stack VM in `compiler` and `vm`. Programs
+    /// using loops or assignment fail to compile.
+    Vm,
+}
+
+impl FromStr for Backend {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "eval" => Ok(Backend::Eval),
+            "vm" => Ok(Backend::Vm),
+            _ => Err(format!("unknown backend: {} (expected eval or vm)", s)),
+        }
+    }
+}
+
+enum SessionState {
+    Eval(Env),
+    Vm {
+        symbols: SymbolTable,
+        constants: Vec<Rc<Object>>,
+        globals: Vec<Option<Rc<Object>>>,
+    },
+}
+
+/// Runs successive programs that share their global bindings, as the REPL does.
+pub struct Session {
+    state: SessionState,
+    max_parse_depth: usize,
+    eval_config: EvalConfig,
+}
+
+impl Session {
+    pub fn new(backend: Backend) -> Self {
+        let state = match backend {
+            Backend::Eval => SessionState::Eval(Rc::new(RefCell::new(Default::default()))),
+            Backend::Vm => SessionState::Vm {
+                symbols: SymbolTable::default(),
+                constants: Vec::new(),
+                globals: Vec::new(),
+            },
+        };
+        Session {
+            state,
+            max_parse_depth: DEFAULT_MAX_DEPTH,
+            eval_config: EvalConfig::default(),
+        }
+    }
+
+    /// Limits how deeply expressions may nest when parsing and how deeply
+    /// function calls may nest when evaluating.
+    pub fn with_max_depth(mut self, max_depth: usize) -> Self {
+        self.max_parse_depth = max_depth;
+        self.eval_config = self.eval_config.with_max_depth(max_depth);
+        self
+    }
+
+    /// Limits how many bytes of stack parsing and evaluation may each use,
+    /// see [`max_stack_for`].
+    pub fn with_max_stack(mut self, max_stack: usize) -> Self {
+        self.eval_config = self.eval_config.with_max_stack(max_stack);
+        self
+    }
+
+    /// Runs programs under `config`. The VM counts each instruction as a
+    /// step. The parser uses its stack limit too.
+    pub fn with_config(mut self, config: EvalConfig) -> Self {
+        self.eval_config = config;
+        self
+    }
+
+    pub fn backend(&self) -> Backend {
+        match self.state {
+            SessionState::Eval(_) => Backend::Eval,
+            SessionState::Vm { .. } => Backend::Vm,
+        }
+    }
+
+    /// Parses and runs `source`. Compile errors from the VM backend are
+    /// reported as runtime errors.
+    pub fn run(&mut self, source: &str) -> Result<Rc<Object>, RunError> {
+        let program = Parser::new(Lexer::new(&strip_shebang(source)))
+            .with_max_depth(self.max_parse_depth)
+            .with_max_stack(self.eval_config.max_stack)
+            .parse_program()
+            .map_err(RunError::Parse)?;
+        let node = Node::Program(program);
+        match &mut self.state {
+            SessionState::Eval(env) => {
+                eval_with(node, env, &self.eval_config).map_err(RunError::Runtime)
+            }
+            SessionState::Vm {
+                symbols,
+                constants,
+                globals,
+            } => {
+                let constant_count = constants.len();
+                let mut compiler =
+                    Compiler::new_with_state(mem::take(symbols), mem::take(constants));
+                let compiled = compiler.compile(&node);
+                let bytecode = compiler.bytecode();
+                if let Err(err) = compiled {
+                    // Nothing refers to the constants of a program that failed
+                    // to compile, so drop them rather than let them pile up.
+                    *symbols = bytecode.symbols;
+                    *constants = bytecode.constants;
+                    constants.truncate(constant_count);
+                    return Err(RunError::Runtime(err));
+                }
+
+                let mut vm = Vm::new_with_globals(bytecode, mem::take(globals));
+                let result = vm.run_with(&self.eval_config);
+                let (bytecode, vm_globals) = vm.into_parts();
+                *symbols = bytecode.symbols;
+                *constants = bytecode.constants;
+                *globals = vm_globals;
+                result.map_err(RunError::Runtime)
+            }
+        }
+    }
+}
+
+/// Parses and evaluates `source` in a fresh environment.
+pub fn run(source: &str) -> Result<Rc<Object>, RunError> {
+    run_with(source, Backend::default())
+}
+
+pub fn run_with(source: &str, backend: Backend) -> Result<Rc<Object>, RunError> {
+    Session::new(backend).run(source)
+}
+
+/// How much of a thread's `stack_size` the parser and the evaluator may use,
+/// leaving the rest for the frames around them.
+pub fn max_stack_for(stack_size: usize) -> usize {
+    stack_size / 4 * 3
+}
+
+/// Runs `f` on a new thread with a stack of `stack_size` bytes and waits for
+/// its result. Deeply recursive programs need more stack than the main thread
+/// usually has to reach the depth limits instead of overflowing.
+pub fn with_stack_size<T, F>(stack_size: usize, f: F) -> io::Result<T>
+where
+    T: Send,
+    F: FnOnce() -> T + Send,
+{
+    thread::scope(|scope| {
+        let handle = thread::Builder::new()
+            .stack_size(stack_size)
+            .spawn_scoped(scope, f)?;
+        Ok(handle
+            .join()
+            .unwrap_or_else(|panic| std::panic::resume_unwind(panic)))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn test_run_program() {
+        let result = run("let add = fn(a, b) { a + b }; add(1, 2)").expect("run");
+        assert_eq!(result.to_string(), "3");
+    }
+
+    #[test]
+    fn test_shebang_is_ignored() {
+        let source = "#!/usr/bin/env interpreter-rust\nlet x = 2;\nx * 21";
+        assert_eq!(run(source).expect("run").to_string(), "42");
+        assert_eq!(strip_shebang("#!/bin/monkey"), "             ");
+        assert_eq!(strip_shebang("#!/bin/sh\n1"), "         \n1");
+        assert_eq!(strip_shebang("1 # 2"), "1 # 2");
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let parse_err = run("let = 5;").expect_err("parse error");
+        let runtime_err = run("1 + true").expect_err("runtime error");
+        assert_eq!(parse_err.exit_code(), EXIT_PARSE_ERROR);
+        assert_eq!(runtime_err.exit_code(), EXIT_RUNTIME_ERROR);
+        assert_ne!(parse_err.exit_code(), runtime_err.exit_code());
+    }
+
+    #[test]
+    fn test_backend_from_str() {
+        assert_eq!("eval".parse(), Ok(Backend::Eval));
+        assert_eq!("vm".parse(), Ok(Backend::Vm));
+        assert!("jit".parse::<Backend>().is_err());
+    }
+
+    #[test]
+    fn test_vm_session_keeps_globals() {
+        let mut session = Session::new(Backend::Vm);
+        session.run("let a = 1;").expect("run");
+        session
+            .run("while (a < 3) { a += 1 }")
+            .expect_err("unsupported");
+        assert_eq!(session.run("a + 1").expect("run").to_string(), "2");
+    }
+
+    #[test]
+    fn test_shebang_keeps_line_numbers() {
+        let source = "#!/usr/bin/env interpreter-rust\nlet x = 1 + true;";
+        let err = run(source).expect_err("runtime error");
+        assert!(err.render(source, false).contains(" --> 2:9"));
+
+        let source = "#!/usr/bin/env interpreter-rust\nlet longname = 1;\nlongname + true";
+        let err = run(source).expect_err("runtime error");
+        let expected = "\
+error[E0203]: type mismatch: 1 + true
+ --> 3:1
+  |
+3 | longname + true
+  | ^^^^^^^^^^^^^^^
+";
+        assert_eq!(err.render(source, false), expected);
+    }
+
+    #[test]
+    fn test_deep_recursion_is_an_error() {
+        let source = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(100000)";
+        // Errors are Send, so they can leave the interpreter thread.
+        let err = with_stack_size(DEFAULT_STACK_SIZE, || {
+            let mut session =
+                Session::new(Backend::Eval).with_max_stack(max_stack_for(DEFAULT_STACK_SIZE));
+            session.run(source).expect_err("too deep")
+        })
+        .expect("spawn");
+        assert_eq!(err.exit_code(), EXIT_RUNTIME_ERROR);
+        assert_eq!(
+            err.to_string(),
+            "maximum recursion depth exceeded: the limit is 1024"
+        );
+    }
+
+    #[test]
+    fn test_recursion_within_the_default_limit() {
+        let source = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(1000)";
+        let result = with_stack_size(DEFAULT_STACK_SIZE, || {
+            let mut session =
+                Session::new(Backend::Eval).with_max_stack(max_stack_for(DEFAULT_STACK_SIZE));
+            session.run(source).expect("recursion").to_string()
+        })
+        .expect("spawn");
+        assert_eq!(result, "1000");
+    }
+
+    #[test]
+    fn test_small_stack_is_an_error() {
+        let stack_size = 2 * 1024 * 1024;
+        let source = "let f = fn(n) { if (n == 0) { 0 } else { 1 + f(n - 1) } }; f(100000)";
+        let deep = format!("{}1", "(".repeat(100_000));
+        let (eval_err, parse_err) = with_stack_size(stack_size, || {
+            let mut session = Session::new(Backend::Eval)
+                .with_max_depth(usize::MAX)
+                .with_max_stack(max_stack_for(stack_size));
+            let eval_err = session.run(source).expect_err("too deep");
+            let parse_err = session.run(&deep).expect_err("too deep");
+            (eval_err, parse_err)
+        })
+        .expect("spawn");
+        assert_eq!(eval_err.exit_code(), EXIT_RUNTIME_ERROR);
+        assert_eq!(parse_err.exit_code(), EXIT_PARSE_ERROR);
+    }
+
+    #[test]
+    fn test_session_max_depth() {
+        let mut session = Session::new(Backend::Eval).with_max_depth(8);
+        assert!(matches!(
+            session.run("((((((((1))))))))"),
+            Err(RunError::Parse(_))
+        ));
+        session
+            .run("let down = fn(n) { if (n == 0) { 0 } else { down(n - 1) } };")
+            .expect("run");
+        let err = session.run("down(10)").expect_err("too deep");
+        assert_eq!(err.exit_code(), EXIT_RUNTIME_ERROR);
+        assert_eq!(session.run("down(1)").expect("run").to_string(), "0");
+    }
+
+    #[test]
+    fn test_session_config() {
+        let config = EvalConfig::default().with_max_steps(1000);
+        let mut session = Session::new(Backend::Eval).with_config(config);
+        let err = session.run("while (true) { }").expect_err("out of steps");
+        assert_eq!(err.exit_code(), EXIT_RUNTIME_ERROR);
+        assert_eq!(err.to_string(), "step budget of 1000 exhausted");
+        assert_eq!(session.run("1 + 1").expect("run").to_string(), "2");
+    }
+
+    #[test]
+    fn test_session_timeout_applies_to_each_run() {
+        let config = EvalConfig::default().with_timeout(Duration::from_millis(20));
+        let mut session = Session::new(Backend::Eval).with_config(config);
+        for _ in 0..3 {
+            let err = session.run("while (true) { }").expect_err("timed out");
+            assert_eq!(err.to_string(), "deadline exceeded");
+            thread::sleep(Duration::from_millis(30));
+            assert_eq!(session.run("1 + 1").expect("run").to_string(), "2");
+        }
+    }
+}
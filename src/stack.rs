@@ -0,0 +1,60 @@
+/// How many bytes of stack the parser and the evaluator may each use when no
+/// other limit is given: three quarters of the 2 MiB Rust gives a spawned
+/// thread. This is what limits nesting in unoptimised builds, whose frames
+/// are several times larger than optimised ones.
+pub const DEFAULT_MAX_STACK: usize = 1536 * 1024;
+
+/// Measures how far the stack has grown since the guard was created, so that
+/// deep recursion can fail with an error before it overflows the thread.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct StackGuard {
+    base: usize,
+    max_stack: usize,
+}
+
+impl StackGuard {
+    /// Starts measuring from the caller's frame.
+    pub(crate) fn new(max_stack: usize) -> Self {
+        StackGuard {
+            base: position(),
+            max_stack,
+        }
+    }
+
+    /// Whether more than `max_stack` bytes are in use since the guard was
+    /// created.
+    pub(crate) fn exhausted(&self) -> bool {
+        position().abs_diff(self.base) > self.max_stack
+    }
+}
+
+/// The address of a local variable, which tracks the stack pointer closely
+/// enough for budgeting.
+#[inline(always)]
+fn position() -> usize {
+    let marker = 0u8;
+    std::hint::black_box(&marker) as *const u8 as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StackGuard;
+
+    fn recurse(guard: &StackGuard, depth: usize) -> usize {
+        let padding = [0u8; 256];
+        std::hint::black_box(&padding);
+        if guard.exhausted() {
+            depth
+        } else {
+            recurse(guard, depth + 1)
+        }
+    }
+
+    #[test]
+    fn test_guard_stops_recursion() {
+        let guard = StackGuard::new(64 * 1024);
+        assert!(!guard.exhausted());
+        let depth = recurse(&guard, 0);
+        assert!(depth > 0 && depth < 64 * 1024 / 256, "{}", depth);
+    }
+}
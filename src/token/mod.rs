@@ -1,9 +1,12 @@
 pub mod token {
     use core::fmt;
 
+    use num_bigint::BigInt;
     use serde::{Deserialize, Serialize};
 
-    #[derive(Clone, Debug, Eq, Hash, Ord, Serialize, Deserialize, PartialOrd, PartialEq)]
+    use crate::span::Span;
+
+    #[derive(Clone, Debug, Serialize, Deserialize, PartialOrd, PartialEq)]
     pub enum Token {
         // Special tokens
         Illegal,
@@ -13,24 +16,40 @@ pub mod token {
         Ident(String),
 
         // Literals
-        Int(i32),
+        Int(i64),
+        /// A decimal integer literal too large for an `i64`.
+        BigInt(BigInt),
+        Float(f64),
         String(String),
         Boolean(bool),
 
         // Operators
-        Assign,   // =
-        Plus,     // +
-        Minus,    // -
-        Bang,     // !
-        Asterisk, // *
-        Slash,    // /
-        NotEq,    // !=
-        Eq,       // ==
-        Lt,       // <
-        Gt,       // >
+        Assign,         // =
+        PlusAssign,     // +=
+        MinusAssign,    // -=
+        AsteriskAssign, // *=
+        SlashAssign,    // /=
+        PercentAssign,  // %=
+        Plus,           // +
+        Minus,          // -
+        Bang,           // !
+        Asterisk,       // *
+        Slash,          // /
+        Percent,        // %
+        Power,          // **
+        NotEq,          // !=
+        Eq,             // ==
+        Lt,             // <
+        Gt,             // >
+        LtEq,           // <=
+        GtEq,           // >=
+        And,            // &&
+        Or,             // ||
+        DotDot,         // ..
 
         // Delimiters
         Comma,
+        Colon,
         Semicolon,
         LParen,
         RParen,
@@ -45,6 +64,35 @@ pub mod token {
         If,
         Else,
         Return,
+        While,
+        For,
+        In,
+        Break,
+        Continue,
+    }
+
+    /// A token together with the location it was read from. `leading_trivia`
+    /// is only filled in when the lexer runs in lossless mode.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct SpannedToken {
+        pub token: Token,
+        pub span: Span,
+        pub leading_trivia: Vec<Trivia>,
+    }
+
+    #[derive(Clone, Copy, Debug, Eq, PartialEq)]
+    pub enum TriviaKind {
+        Whitespace,
+        LineComment,
+        BlockComment,
+    }
+
+    /// Source text between tokens that the parser ignores.
+    #[derive(Clone, Debug, PartialEq)]
+    pub struct Trivia {
+        pub kind: TriviaKind,
+        pub text: String,
+        pub span: Span,
     }
 
     impl fmt::Display for Token {
@@ -57,17 +105,32 @@ pub mod token {
                 Token::String(s) => write!(f, "{}", s),
                 Token::Ident(s) => write!(f, "{}", s),
                 Token::Int(i) => write!(f, "{}", i),
+                Token::BigInt(b) => write!(f, "{}", b),
+                Token::Float(x) => write!(f, "{:?}", x),
                 Token::Assign => write!(f, "="),
+                Token::PlusAssign => write!(f, "+="),
+                Token::MinusAssign => write!(f, "-="),
+                Token::AsteriskAssign => write!(f, "*="),
+                Token::SlashAssign => write!(f, "/="),
+                Token::PercentAssign => write!(f, "%="),
                 Token::Plus => write!(f, "+"),
                 Token::Minus => write!(f, "-"),
                 Token::Bang => write!(f, "!"),
                 Token::Asterisk => write!(f, "*"),
                 Token::Slash => write!(f, "/"),
+                Token::Percent => write!(f, "%"),
+                Token::Power => write!(f, "**"),
                 Token::NotEq => write!(f, "!="),
                 Token::Eq => write!(f, "=="),
                 Token::Lt => write!(f, "<"),
                 Token::Gt => write!(f, ">"),
+                Token::LtEq => write!(f, "<="),
+                Token::GtEq => write!(f, ">="),
+                Token::And => write!(f, "&&"),
+                Token::Or => write!(f, "||"),
+                Token::DotDot => write!(f, ".."),
                 Token::Comma => write!(f, ","),
+                Token::Colon => write!(f, ":"),
                 Token::Semicolon => write!(f, ";"),
                 Token::LParen => write!(f, "("),
                 Token::RParen => write!(f, ")"),
@@ -79,6 +142,11 @@ pub mod token {
                 Token::Let => write!(f, "let"),
                 Token::If => write!(f, "if"),
                 Token::Else => write!(f, "else"),
+                Token::While => write!(f, "while"),
+                Token::For => write!(f, "for"),
+                Token::In => write!(f, "in"),
+                Token::Break => write!(f, "break"),
+                Token::Continue => write!(f, "continue"),
             }
         }
     }
@@ -94,7 +162,9 @@ pub mod token {
                 '!' => Ok(Token::Bang),
                 '*' => Ok(Token::Asterisk),
                 '/' => Ok(Token::Slash),
+                '%' => Ok(Token::Percent),
                 ',' => Ok(Token::Comma),
+                ':' => Ok(Token::Colon),
                 ';' => Ok(Token::Semicolon),
                 '(' => Ok(Token::LParen),
                 ')' => Ok(Token::RParen),
@@ -115,6 +185,13 @@ pub mod token {
             "if" => Token::If,
             "else" => Token::Else,
             "return" => Token::Return,
+            "while" => Token::While,
+            "for" => Token::For,
+            "in" => Token::In,
+            "break" => Token::Break,
+            "continue" => Token::Continue,
+            "true" => Token::Boolean(true),
+            "false" => Token::Boolean(false),
             _ => Token::Ident(ident.to_string()),
         }
     }
@@ -0,0 +1,694 @@
+use std::collections::BTreeMap;
+use std::mem;
+use std::rc::Rc;
+
+use crate::code::{self, OpCode};
+use crate::compiler::Bytecode;
+use crate::eval::builtin;
+use crate::eval::error::{EvaluatorError, EvaluatorErrorKind, Frame, Operand};
+use crate::eval::limits::{self, Depth, EvalConfig};
+use crate::eval::{
+    eval_index_expression, eval_infix_expression, eval_prefix_expression, hash_key, is_truthy,
+    EvaluatorResult,
+};
+use crate::object::{CompiledFunction, Object};
+use crate::token::token::Token;
+
+/// A stack machine that executes the output of `compiler::Compiler`.
+///
+/// Operators, indexing and builtins are delegated to the tree-walking
+/// evaluator so that both backends agree on results and error messages.
+pub struct Vm {
+    bytecode: Bytecode,
+    stack: Vec<Rc<Object>>,
+    globals: Vec<Option<Rc<Object>>>,
+    last_popped: Rc<Object>,
+    /// The function running now, starting with the program itself.
+    frame: CallFrame,
+    /// The functions waiting for a call to return, innermost last.
+    callers: Vec<CallFrame>,
+}
+
+/// A call in progress.
+struct CallFrame {
+    /// The closure being run, for `OpCode::CurrentClosure` and free variables.
+    closure: Rc<Object>,
+    function: Rc<CompiledFunction>,
+    ip: usize,
+    /// Where the call's locals start on the stack.
+    base_pointer: usize,
+    /// Counts the call against the depth limit until it returns. The program
+    /// itself has none.
+    _depth: Option<Depth>,
+}
+
+impl CallFrame {
+    fn new(closure: Rc<Object>, function: Rc<CompiledFunction>, base_pointer: usize) -> Self {
+        CallFrame {
+            closure,
+            function,
+            ip: 0,
+            base_pointer,
+            _depth: None,
+        }
+    }
+}
+
+impl Vm {
+    pub fn new(bytecode: Bytecode) -> Self {
+        Vm::new_with_globals(bytecode, Vec::new())
+    }
+
+    pub fn new_with_globals(mut bytecode: Bytecode, globals: Vec<Option<Rc<Object>>>) -> Self {
+        let program = Rc::new(CompiledFunction {
+            instructions: mem::take(&mut bytecode.instructions),
+            spans: mem::take(&mut bytecode.spans),
+            parameters: Vec::new(),
+            num_locals: 0,
+            name: None,
+        });
+        let closure = Rc::new(Object::Closure(Rc::clone(&program), Vec::new()));
+        Vm {
+            bytecode,
+            stack: Vec::new(),
+            globals,
+            last_popped: Rc::new(Object::Null),
+            frame: CallFrame::new(closure, program, 0),
+            callers: Vec::new(),
+        }
+    }
+
+    /// Hands back the compiled state and globals so a later run can reuse them.
+    pub fn into_parts(self) -> (Bytecode, Vec<Option<Rc<Object>>>) {
+        (self.bytecode, self.globals)
+    }
+
+    pub fn last_popped(&self) -> Rc<Object> {
+        Rc::clone(&self.last_popped)
+    }
+
+    /// Runs the program and returns the value of its last expression
+    /// statement, or the operand of a top-level `return`.
+    pub fn run(&mut self) -> EvaluatorResult {
+        self.run_with(&EvalConfig::default())
+    }
+
+    /// Runs the program under the limits in `config`. Every instruction takes
+    /// a step, and function calls count against the depth limit.
+    pub fn run_with(&mut self, config: &EvalConfig) -> EvaluatorResult {
+        let _config = limits::install(config);
+        // Only the program can run off its end; functions always return.
+        while self.frame.ip < self.frame.function.instructions.len() {
+            let start = self.frame.ip;
+            match self.step() {
+                Ok(Some(value)) => return Ok(value),
+                Ok(None) => (),
+                Err(err) => return Err(self.unwind(err, start)),
+            }
+        }
+        Ok(self.last_popped())
+    }
+
+    /// Points `err` at the instruction at `start` in the running function and
+    /// records a frame for each call it unwinds out of.
+    fn unwind(&self, err: EvaluatorError, start: usize) -> EvaluatorError {
+        let mut err = match self.frame.function.span_at(start) {
+            Some(span) => err.or_span(span),
+            None => err,
+        };
+        let mut callee = &self.frame;
+        for caller in self.callers.iter().rev() {
+            // A caller's ip is just past the call instruction it is waiting on.
+            if let Some(call_site) = caller.function.span_at(caller.ip - 1) {
+                let name = callee.function.name.as_deref().unwrap_or("<anonymous>");
+                err = err.with_frame(Frame::new(name, call_site));
+            }
+            callee = caller;
+        }
+        err
+    }
+
+    /// Executes the instruction at the running function's ip, returning a
+    /// value when the program halts.
+    fn step(&mut self) -> Result<Option<Rc<Object>>, EvaluatorError> {
+        limits::step()?;
+        let byte = self.frame.function.instructions[self.frame.ip];
+        let op = OpCode::from_byte(byte)
+            .ok_or_else(|| EvaluatorError::new(EvaluatorErrorKind::UnknownOpcode(byte)))?;
+        self.frame.ip += 1;
+
+        match op {
+            OpCode::Constant => {
+                let index = self.read_operand(2);
+                let constant = Rc::clone(&self.bytecode.constants[index]);
+                self.stack.push(constant);
+            }
+            OpCode::Pop => {
+                self.last_popped = self.pop()?;
+            }
+            OpCode::True => self.stack.push(Rc::new(Object::Boolean(true))),
+            OpCode::False => self.stack.push(Rc::new(Object::Boolean(false))),
+            OpCode::Null => self.stack.push(Rc::new(Object::Null)),
+            OpCode::Add
+            | OpCode::Sub
+            | OpCode::Mul
+            | OpCode::Div
+            | OpCode::Equal
+            | OpCode::NotEqual
+            | OpCode::GreaterThan
+            | OpCode::LessThan
+            | OpCode::GreaterEqual
+            | OpCode::LessEqual
+            | OpCode::Mod
+            | OpCode::Pow => {
+                let right = self.pop()?;
+                let left = self.pop()?;
+                let result = eval_infix_expression(&infix_token(op), &left, &right)?;
+                self.stack.push(result);
+            }
+            OpCode::Minus | OpCode::Bang => {
+                let operand = self.pop()?;
+                let token = if op == OpCode::Minus {
+                    Token::Minus
+                } else {
+                    Token::Bang
+                };
+                let result = eval_prefix_expression(&token, &operand)?;
+                self.stack.push(result);
+            }
+            OpCode::Jump => {
+                self.frame.ip = self.read_operand(2);
+            }
+            OpCode::JumpNotTruthy => {
+                let target = self.read_operand(2);
+                let condition = self.pop()?;
+                if !is_truthy(&condition) {
+                    self.frame.ip = target;
+                }
+            }
+            OpCode::SetGlobal => {
+                let index = self.read_operand(2);
+                let value = self.pop()?;
+                if self.globals.len() <= index {
+                    self.globals.resize(index + 1, None);
+                }
+                self.globals[index] = Some(value);
+            }
+            OpCode::GetGlobal => {
+                let index = self.read_operand(2);
+                match self.globals.get(index).cloned().flatten() {
+                    Some(value) => self.stack.push(value),
+                    None => {
+                        let name = self.bytecode.symbols.name(index).unwrap_or("<unknown>");
+                        let name = name.to_string();
+                        return Err(EvaluatorErrorKind::UnknownIdentifier(name).into());
+                    }
+                }
+            }
+            OpCode::ReturnValue => {
+                let value = self.pop()?;
+                let Some(caller) = self.callers.pop() else {
+                    return Ok(Some(value));
+                };
+                // Drop the callee and its locals along with the frame.
+                self.stack.truncate(self.frame.base_pointer - 1);
+                self.frame = caller;
+                self.stack.push(value);
+            }
+            OpCode::Array => {
+                let count = self.read_operand(2);
+                let elements = self.pop_many(count)?;
+                self.stack.push(Rc::new(Object::Array(elements)));
+            }
+            OpCode::Hash => {
+                let count = self.read_operand(2);
+                let items = self.pop_many(count)?;
+                let mut hash = BTreeMap::new();
+                for pair in items.chunks_exact(2) {
+                    let (key, value) = (&pair[0], &pair[1]);
+                    hash.insert(hash_key(key)?, (Rc::clone(key), Rc::clone(value)));
+                }
+                self.stack.push(Rc::new(Object::Hash(hash)));
+            }
+            OpCode::Index => {
+                let index = self.pop()?;
+                let left = self.pop()?;
+                let result = eval_index_expression(&left, &index)?;
+                self.stack.push(result);
+            }
+            OpCode::Call => {
+                let count = self.read_operand(1);
+                self.call(count)?;
+            }
+            OpCode::GetLocal => {
+                let index = self.read_operand(1);
+                let value = Rc::clone(&self.stack[self.frame.base_pointer + index]);
+                self.stack.push(value);
+            }
+            OpCode::SetLocal => {
+                let index = self.read_operand(1);
+                let value = self.pop()?;
+                self.stack[self.frame.base_pointer + index] = value;
+            }
+            OpCode::GetBuiltin => {
+                let index = self.read_operand(1);
+                let builtin = builtin::get(index).ok_or_else(|| {
+                    EvaluatorError::from(EvaluatorErrorKind::UnknownIdentifier(
+                        "<unknown>".to_string(),
+                    ))
+                })?;
+                self.stack.push(builtin);
+            }
+            OpCode::Closure => {
+                let index = self.read_operand(2);
+                let count = self.read_operand(1);
+                let free = self.pop_many(count)?;
+                let constant = &self.bytecode.constants[index];
+                let closure = match constant.as_ref() {
+                    Object::Closure(function, _) if !free.is_empty() => {
+                        Rc::new(Object::Closure(Rc::clone(function), free))
+                    }
+                    Object::Closure(..) => Rc::clone(constant),
+                    _ => {
+                        return Err(EvaluatorErrorKind::NotAFunction(Operand::new(constant)).into())
+                    }
+                };
+                self.stack.push(closure);
+            }
+            OpCode::GetFree => {
+                let index = self.read_operand(1);
+                let value = match self.frame.closure.as_ref() {
+                    Object::Closure(_, free) => free.get(index).map(Rc::clone),
+                    _ => None,
+                };
+                let value =
+                    value.ok_or_else(|| EvaluatorError::new(EvaluatorErrorKind::StackUnderflow))?;
+                self.stack.push(value);
+            }
+            OpCode::CurrentClosure => {
+                let closure = Rc::clone(&self.frame.closure);
+                self.stack.push(closure);
+            }
+        }
+        Ok(None)
+    }
+
+    /// Calls the function below the `count` arguments on top of the stack.
+    /// Closures get a new frame whose locals start with the arguments;
+    /// builtins run straight away.
+    fn call(&mut self, count: usize) -> Result<(), EvaluatorError> {
+        let callee_slot = self
+            .stack
+            .len()
+            .checked_sub(count + 1)
+            .ok_or_else(|| EvaluatorError::new(EvaluatorErrorKind::StackUnderflow))?;
+        let callee = Rc::clone(&self.stack[callee_slot]);
+        match callee.as_ref() {
+            Object::Closure(function, _) => {
+                if function.parameters.len() != count {
+                    return Err(EvaluatorErrorKind::WrongArgumentCount {
+                        expected: function.parameters.len(),
+                        got: count,
+                    }
+                    .into());
+                }
+                let depth = limits::call()?;
+                let base_pointer = callee_slot + 1;
+                self.stack
+                    .resize(base_pointer + function.num_locals, Rc::new(Object::Null));
+                let mut frame =
+                    CallFrame::new(Rc::clone(&callee), Rc::clone(function), base_pointer);
+                frame._depth = Some(depth);
+                self.callers.push(mem::replace(&mut self.frame, frame));
+            }
+            Object::Builtin(_, builtin) => {
+                let args = self.stack.split_off(callee_slot + 1);
+                self.stack.truncate(callee_slot);
+                let result = builtin(args)?;
+                self.stack.push(result);
+            }
+            _ => return Err(EvaluatorErrorKind::NotAFunction(Operand::new(&callee)).into()),
+        }
+        Ok(())
+    }
+
+    fn read_operand(&mut self, width: usize) -> usize {
+        let operand = code::read_operand(&self.frame.function.instructions, self.frame.ip, width);
+        self.frame.ip += width;
+        operand
+    }
+
+    fn pop(&mut self) -> Result<Rc<Object>, EvaluatorError> {
+        self.stack
+            .pop()
+            .ok_or_else(|| EvaluatorError::new(EvaluatorErrorKind::StackUnderflow))
+    }
+
+    /// Pops the top `count` values, keeping them in the order they were pushed.
+    fn pop_many(&mut self, count: usize) -> Result<Vec<Rc<Object>>, EvaluatorError> {
+        let start = self
+            .stack
+            .len()
+            .checked_sub(count)
+            .ok_or_else(|| EvaluatorError::new(EvaluatorErrorKind::StackUnderflow))?;
+        Ok(self.stack.split_off(start))
+    }
+}
+
+fn infix_token(op: OpCode) -> Token {
+    match op {
+        OpCode::Add => Token::Plus,
+        OpCode::Sub => Token::Minus,
+        OpCode::Mul => Token::Asterisk,
+        OpCode::Div => Token::Slash,
+        OpCode::Equal => Token::Eq,
+        OpCode::NotEqual => Token::NotEq,
+        OpCode::GreaterThan => Token::Gt,
+        OpCode::LessThan => Token::Lt,
+        OpCode::GreaterEqual => Token::GtEq,
+        OpCode::LessEqual => Token::LtEq,
+        OpCode::Mod => Token::Percent,
+        OpCode::Pow => Token::Power,
+        _ => unreachable!("{:?} is not an infix operator", op),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Vm;
+    use crate::compiler::Compiler;
+    use crate::eval::error::EvaluatorErrorKind;
+    use crate::eval::limits::{EvalConfig, Limit};
+    use crate::eval::EvaluatorResult;
+    use crate::parser::parser::parse;
+    use crate::runner::{Backend, Session};
+
+    fn run(input: &str, config: &EvalConfig) -> EvaluatorResult {
+        let mut compiler = Compiler::new();
+        compiler.compile(&parse(input).expect("parse"))?;
+        Vm::new(compiler.bytecode()).run_with(config)
+    }
+
+    /// Runs every input through both backends and checks they agree with
+    /// `expected`, comparing error messages for programs that fail.
+    fn apply_test(test_case: &[(&str, &str)]) {
+        for backend in [Backend::Eval, Backend::Vm] {
+            let mut session = Session::new(backend);
+            for (input, expected) in test_case {
+                let actual = match session.run(input) {
+                    Ok(value) => value.to_string(),
+                    Err(err) => err.to_string(),
+                };
+                assert_eq!(&actual, expected, "{:?} backend on {:?}", backend, input);
+            }
+        }
+    }
+
+    #[test]
+    fn test_integer_arithmetic() {
+        let test_case = [
+            ("5", "5"),
+            ("10", "10"),
+            ("-5", "-5"),
+            ("-10", "-10"),
+            ("5+5", "10"),
+            ("10+20", "30"),
+            ("50 / 2 * 2 + 10 - 5", "55"),
+            ("5 * (2 + 10)", "60"),
+            ("-50 + 100 + -50", "0"),
+            ("99999999999999999999", "99999999999999999999"),
+            ("-9223372036854775808", "-9223372036854775808"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_boolean_expressions() {
+        let test_case = [
+            ("true", "true"),
+            ("false", "false"),
+            ("1 < 2", "true"),
+            ("1<1", "false"),
+            ("1 > 2", "false"),
+            ("1 == 1", "true"),
+            ("1 != 1", "false"),
+            ("true != false", "true"),
+            ("(1 < 2) == true", "true"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_comparison_and_arithmetic_operators() {
+        let test_case = [
+            ("1 <= 2", "true"),
+            ("3 <= 2", "false"),
+            ("2 >= 2", "true"),
+            ("7 % 3", "1"),
+            ("2 ** 3 ** 2", "512"),
+            ("-2 ** 2", "-4"),
+            ("2 ** -1", "negative exponent: 2 ** -1"),
+            ("1 / 0", "division by zero: 1 / 0"),
+            ("9223372036854775807 + 1", "9223372036854775808"),
+            ("2 ** 64 / 2 ** 62", "4"),
+            ("1 + 0.5", "1.5"),
+            ("-2.5 * 2", "-5.0"),
+            (r#""apple" < "banana""#, "true"),
+            (r#""b" >= "abc""#, "true"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_strings() {
+        let test_case = [
+            (r#""monkey""#, "monkey"),
+            (r#""mon" + "key""#, "monkey"),
+            (r#""a" == "a""#, "true"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let test_case = [
+            ("if (true) { 10 }", "10"),
+            ("if (false) { 10 }", "null"),
+            ("if (1) { 10 }", "10"),
+            ("if (1 < 2) { 10 }", "10"),
+            ("if (1 > 2) { 10 }", "null"),
+            ("if (1 > 2) { 10 } else { 20 }", "20"),
+            ("if (1 < 2) { 10 } else { 20 }", "10"),
+            ("if (true) { }", "null"),
+            ("if (true) { let z = 1; }", "null"),
+            ("!(if (false) { 5; })", "true"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_logical_operators() {
+        let test_case = [
+            ("true && true", "true"),
+            ("true && false", "false"),
+            ("false && true", "false"),
+            ("false || true", "true"),
+            ("false || false", "false"),
+            ("true || false", "true"),
+            ("1 && \"\"", "true"),
+            ("if (false) { 1 } || 0", "true"),
+            ("1 < 2 && 2 < 3 || false", "true"),
+            ("false && 1 + true", "false"),
+            ("true || 1 + true", "true"),
+            ("true && 1 + true", "type mismatch: 1 + true"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_prefix_operators() {
+        let test_case = [
+            ("!true", "false"),
+            ("!false", "true"),
+            ("!5", "false"),
+            ("!!true", "true"),
+            ("!!false", "false"),
+            ("!!5", "true"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_return_statements() {
+        let test_case = [
+            ("return 10;", "10"),
+            ("return 10; 9;", "10"),
+            ("return 2 * 5; 9;", "10"),
+            ("9; return 2 * 5; 9;", "10"),
+            ("if (10 > 1) { if (10 > 1) { return 10; } return 1; }", "10"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_let_statements() {
+        let test_case = [
+            ("let one = 1; one", "1"),
+            ("let one = 1; let two = one + one; one + two", "3"),
+            ("let a = 5; let a = a * 2; a;", "10"),
+            ("let x = 1;", "null"),
+            ("let y = 5 + true;", "type mismatch: 5 + true"),
+            ("y", "identifier not found: y"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_error_handling() {
+        let test_case = [
+            ("5 + true;", "type mismatch: 5 + true"),
+            ("5 + true; 5;", "type mismatch: 5 + true"),
+            ("-true", "unknown operator: -true"),
+            ("true + false;", "unknown operator: true + false"),
+            (
+                "true + false + true + false;",
+                "unknown operator: true + false",
+            ),
+            ("5; true + false; 5", "unknown operator: true + false"),
+            (r#""Hello" - "World""#, "unknown operator: Hello - World"),
+            ("foobar", "identifier not found: foobar"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_functions() {
+        let test_case = [
+            ("let f = fn(x) { x }; f(1)", "1"),
+            ("fn() { }()", "null"),
+            ("let k = fn() { let a = 1; let b = 2; a + b }; k()", "3"),
+            ("let g = fn(a, b) { let c = a * b; c - a }; g(3, 4)", "9"),
+            ("let h = fn() { if (true) { return 1; } 2 }; h() + 10", "11"),
+            ("f(1) + g(1, 1)", "1"),
+            ("f(1, 2)", "wrong number of arguments: expected 1, got 2"),
+            ("true()", "not a function: true"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_closures() {
+        let test_case = [
+            (
+                "let adder = fn(a) { fn(b) { fn(c) { a + b + c } } }; adder(1)(2)(3)",
+                "6",
+            ),
+            (
+                "let counter = fn(n) { let next = fn(i) { if (i == n) { i } else { next(i + 1) } }; next(0) }; counter(5)",
+                "5",
+            ),
+            (
+                "let fib = fn(n) { if (n < 2) { n } else { fib(n - 1) + fib(n - 2) } }; fib(15)",
+                "610",
+            ),
+            ("let wrap = fn(x) { let y = x * 2; fn() { [x, y] } }; wrap(4)()", "[4, 8]"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_collections_and_builtins() {
+        let test_case = [
+            ("[1, 2][0]", "1"),
+            ("let a = [1, [2, 3]]; a[1][0] + len(a)", "4"),
+            (r#"{"a": [1, 2]}["a"][1]"#, "2"),
+            ("let map = fn(xs, f) { if (len(xs) == 0) { [] } else { push(map(rest(xs), f), f(first(xs))) } }; map([1, 2, 3], fn(x) { x * 10 })", "[30, 20, 10]"),
+            ("puts(1)", "null"),
+            ("let f = len; f([1, 2])", "2"),
+        ];
+        apply_test(&test_case);
+    }
+
+    #[test]
+    fn test_call_limits() {
+        let config = EvalConfig::default().with_max_depth(50);
+        let down = "let down = fn(n) { if (n == 0) { 0 } else { down(n - 1) } };";
+        let shallow = run(&format!("{} down(10)", down), &config).expect("shallow");
+        assert_eq!(shallow.to_string(), "0");
+
+        let err = run(&format!("{} down(100)", down), &config).expect_err("too deep");
+        assert!(matches!(err.kind(), EvaluatorErrorKind::RecursionLimit(50)));
+        assert_eq!(err.trace().len(), 50);
+        assert!(err.trace().iter().all(|frame| frame.function == "down"));
+
+        // Calls nowhere near the limit run in a loop, not on the Rust stack.
+        let deep = run(
+            &format!("{} down(100000)", down),
+            &EvalConfig::default().with_max_depth(usize::MAX),
+        );
+        assert_eq!(deep.expect("deep").to_string(), "0");
+
+        let config = EvalConfig::default().with_max_steps(100);
+        let err = run(&format!("{} down(100)", down), &config).expect_err("too long");
+        assert!(matches!(
+            err.kind(),
+            EvaluatorErrorKind::LimitExceeded(Limit::Steps(100))
+        ));
+    }
+
+    #[test]
+    fn test_call_stack_trace() {
+        let input = "let inner = fn(x) { 10 / x };
+let outer = fn(x) { inner(x) };
+outer(0)";
+        let err = run(input, &EvalConfig::default()).expect_err("division by zero");
+        let frames: Vec<(&str, usize, usize)> = err
+            .trace()
+            .iter()
+            .map(|frame| {
+                let span = frame.call_site;
+                (frame.function.as_str(), span.line, span.column)
+            })
+            .collect();
+        assert_eq!(frames, vec![("inner", 2, 21), ("outer", 3, 1)]);
+        assert_eq!(err.span().map(|span| span.line), Some(1));
+
+        let err = run("fn() { -true }()", &EvalConfig::default()).expect_err("prefix");
+        assert_eq!(err.trace().len(), 1);
+        assert_eq!(err.trace()[0].function, "<anonymous>");
+
+        // Errors raised before a body runs belong to the caller.
+        let err = run("len(1)", &EvalConfig::default()).expect_err("builtin");
+        assert!(err.trace().is_empty());
+    }
+
+    #[test]
+    fn test_too_many_constants() {
+        let mut session = Session::new(Backend::Vm);
+        let err = session.run(&"1;".repeat(70_000)).expect_err("too large");
+        assert_eq!(
+            err.to_string(),
+            "program too large for the bytecode compiler: operand 65536 exceeds 65535"
+        );
+        // The failed program's constants are dropped, so later ones still
+        // refer to the right values.
+        assert_eq!(
+            session
+                .run("let y = 5; y + true")
+                .expect_err("type")
+                .to_string(),
+            "type mismatch: 5 + true"
+        );
+    }
+
+    #[test]
+    fn test_runtime_error_span() {
+        let mut session = Session::new(Backend::Vm);
+        let err = session
+            .run("let x = 1;\nx + true")
+            .expect_err("type mismatch");
+        assert!(err
+            .render("let x = 1;\nx + true", false)
+            .contains(" --> 2:1"));
+    }
+}